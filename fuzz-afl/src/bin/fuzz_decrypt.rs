@@ -0,0 +1,17 @@
+use std::io::Read;
+
+use afl::fuzz;
+use zorn_core::identity::{ZornIdentity, ZornIdentitySecret};
+use zorn_core::stream::Decryptor;
+
+// The same fixed key pair as the libFuzzer targets, so that their seeds apply
+fn main() {
+    let recipient_secret = ZornIdentitySecret::from([2u8; 32]);
+    let sender_identity = ZornIdentity::from(&ZornIdentitySecret::from([1u8; 32]));
+
+    fuzz!(|data: &[u8]| {
+        if let Ok(mut d) = Decryptor::new(data, &recipient_secret, &sender_identity) {
+            let _ = d.read_to_end(&mut Vec::new());
+        }
+    });
+}
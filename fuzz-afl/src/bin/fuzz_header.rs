@@ -0,0 +1,10 @@
+use afl::fuzz;
+use zorn_core::header::{Header, HEADER_LEN};
+
+fn main() {
+    fuzz!(|data: &[u8]| {
+        if let Ok(header) = Header::read_from(data) {
+            assert_eq!(&header.to_bytes()[..], &data[..HEADER_LEN]);
+        }
+    });
+}
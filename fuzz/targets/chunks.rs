@@ -0,0 +1,118 @@
+#![no_main]
+use std::io::Read;
+
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+use rand_chacha::ChaCha20Rng;
+use rand_chacha::rand_core::SeedableRng;
+use zorn_core::chunk::{ChunkEncryptor, CHUNK_SIZE, ENCRYPTED_CHUNK_SIZE};
+use zorn_core::identity::ZornIdentity;
+use zorn_core::stream::Decryptor;
+
+mod common;
+
+#[derive(Arbitrary, Debug)]
+enum Mutation {
+    FlipBit { chunk: u8, offset: u32, bit: u8 },
+    Truncate { chunk: u8, offset: u32 },
+    Drop { chunk: u8 },
+    Duplicate { chunk: u8 },
+    Swap { a: u8, b: u8 },
+    Append { data: Vec<u8> },
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    full_chunks: u8,
+    tail: u16,
+    mutations: Vec<Mutation>,
+}
+
+fn plaintext(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+fn encrypt(plaintext: &[u8]) -> (Vec<u8>, Vec<Vec<u8>>) {
+    let mut e = ChunkEncryptor::new(ChaCha20Rng::seed_from_u64(0), &common::sender_secret(), &ZornIdentity::from(&common::recipient_secret()))
+        .expect("Key exchange should succeed, but");
+    let mut chunks = Vec::new();
+    let mut buffer = vec![0u8; ENCRYPTED_CHUNK_SIZE];
+    let mut rest = plaintext;
+    loop {
+        let len = rest.len().min(CHUNK_SIZE);
+        let last = rest.len() <= CHUNK_SIZE;
+        buffer[..len].copy_from_slice(&rest[..len]);
+        let n = e.seal_chunk(&mut buffer, len, last).expect("Chunk should be valid, but");
+        chunks.push(buffer[..n].to_vec());
+        rest = &rest[len..];
+        if last {
+            break;
+        }
+    }
+    (e.header().to_vec(), chunks)
+}
+
+fn apply(chunks: &mut Vec<Vec<u8>>, mutation: &Mutation) {
+    if chunks.is_empty() {
+        return;
+    }
+    let count = chunks.len();
+    let pick = |i: u8| i as usize % count;
+    match *mutation {
+        Mutation::FlipBit { chunk, offset, bit } => {
+            let chunk = &mut chunks[pick(chunk)];
+            if let Some(offset) = (offset as usize).checked_rem(chunk.len()) {
+                chunk[offset] ^= 1 << (bit % 8);
+            }
+        }
+        Mutation::Truncate { chunk, offset } => {
+            let chunk = &mut chunks[pick(chunk)];
+            chunk.truncate((offset as usize).checked_rem(chunk.len()).unwrap_or(0));
+        }
+        Mutation::Drop { chunk } => {
+            chunks.remove(pick(chunk));
+        }
+        Mutation::Duplicate { chunk } => {
+            let i = pick(chunk);
+            chunks.insert(i, chunks[i].clone());
+        }
+        Mutation::Swap { a, b } => {
+            let (a, b) = (pick(a), pick(b));
+            chunks.swap(a, b);
+        }
+        Mutation::Append { ref data } => {
+            chunks.push(data.clone());
+        }
+    }
+}
+
+// Whatever the mutations, the decryptor must only ever release a prefix of the
+// original plaintext, and any change to the ciphertext must be detected
+fuzz_target!(|input: Input| {
+    let plaintext = plaintext((input.full_chunks % 4) as usize * CHUNK_SIZE + input.tail as usize % (CHUNK_SIZE + 1));
+    let (header, mut chunks) = encrypt(&plaintext);
+    let original = [header.clone(), chunks.concat()].concat();
+    for mutation in &input.mutations {
+        apply(&mut chunks, mutation);
+    }
+    let file = [header, chunks.concat()].concat();
+
+    let mut d = Decryptor::new(&file[..], &common::recipient_secret(), &ZornIdentity::from(&common::sender_secret()))
+        .expect("Header should be valid, but");
+    let mut released = Vec::new();
+    let mut buf = [0u8; 4096];
+    let result = loop {
+        match d.read(&mut buf) {
+            Ok(0) => break Ok(()),
+            Ok(n) => released.extend_from_slice(&buf[..n]),
+            Err(e) => break Err(e),
+        }
+    };
+
+    assert!(plaintext.starts_with(&released), "Decryptor released plaintext that was never encrypted");
+    if file == original {
+        assert!(result.is_ok() && released == plaintext, "Unmodified file failed to decrypt");
+    } else {
+        assert!(result.is_err(), "Modified file decrypted without an error");
+    }
+});
@@ -0,0 +1,10 @@
+// A fixed key pair, so that crashes reproduce and seed files stay valid
+use zorn_core::identity::ZornIdentitySecret;
+
+pub fn sender_secret() -> ZornIdentitySecret {
+    ZornIdentitySecret::from([1u8; 32])
+}
+
+pub fn recipient_secret() -> ZornIdentitySecret {
+    ZornIdentitySecret::from([2u8; 32])
+}
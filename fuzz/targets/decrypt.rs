@@ -0,0 +1,14 @@
+#![no_main]
+use std::io::Read;
+
+use libfuzzer_sys::fuzz_target;
+use zorn_core::identity::ZornIdentity;
+use zorn_core::stream::Decryptor;
+
+mod common;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(mut d) = Decryptor::new(data, &common::recipient_secret(), &ZornIdentity::from(&common::sender_secret())) {
+        let _ = d.read_to_end(&mut Vec::new());
+    }
+});
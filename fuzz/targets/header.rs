@@ -0,0 +1,9 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use zorn_core::header::{Header, HEADER_LEN};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(header) = Header::read_from(data) {
+        assert_eq!(&header.to_bytes()[..], &data[..HEADER_LEN]);
+    }
+});
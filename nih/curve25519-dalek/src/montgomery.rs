@@ -162,6 +162,63 @@ impl MontgomeryPoint {
 
         CompressedEdwardsY(y_bytes).decompress()
     }
+
+    /// Map a representative to a `MontgomeryPoint` using Elligator2.
+    ///
+    /// Every 32-byte string is a representative; the high bit of the last
+    /// byte is ignored.
+    pub fn from_representative(representative: &[u8; 32]) -> MontgomeryPoint {
+        elligator_encode(&FieldElement::from_bytes(representative))
+    }
+
+    /// Find a representative of this point, the inverse of
+    /// `from_representative`.
+    ///
+    /// # Inputs
+    ///
+    /// * `tweak`: bit 0 selects the sign of the representative, bit 1
+    ///   which of the two Elligator2 branches it is taken from, and bit 7
+    ///   is copied into the otherwise unused high bit.  Each point with a
+    ///   representative has four of them, so for a uniformly random
+    ///   `tweak` the result is indistinguishable from random bytes.
+    ///
+    /// # Return
+    ///
+    /// * `Some([u8; 32])` if `self` is the image of the Elligator2 map,
+    ///   which holds for about half of the points on the curve and none on
+    ///   the twist;
+    ///
+    /// * `None` otherwise.
+    ///
+    pub fn to_representative(&self, tweak: u8) -> Option<[u8; 32]> {
+        // The map sends r to u = d if d^3 + Ad^2 + d is square and to
+        // u = -d - A otherwise, where d = -A/(1 + 2r^2).  Solving for r
+        // gives r^2 = -(u + A)/(2u) in the first case and
+        // r^2 = -u/(2(u + A)) in the second.
+        let u = FieldElement::from_bytes(&self.0);
+        let u_plus_A = &u + &MONTGOMERY_A;
+        let second_branch = Choice::from((tweak >> 1) & 1);
+
+        let mut numerator = -&u_plus_A;
+        let mut denominator = &u + &u;
+        numerator.conditional_assign(&-&u, second_branch);
+        denominator.conditional_assign(&(&u_plus_A + &u_plus_A), second_branch);
+
+        let (is_square, mut r) = FieldElement::sqrt_ratio_i(&numerator, &denominator);
+        r.conditional_negate(Choice::from(tweak & 1));
+
+        // Which branch the map takes depends on u being on the curve, so
+        // check that r really maps back to this point
+        let maps_back = elligator_encode(&r).ct_eq(self);
+
+        let mut bytes = r.to_bytes();
+        bytes[31] |= tweak & 0x80;
+        if (is_square & maps_back).into() {
+            Some(bytes)
+        } else {
+            None
+        }
+    }
 }
 
 /// Perform the Elligator2 mapping to a Montgomery point.
@@ -476,4 +533,58 @@ mod test {
         let eg = elligator_encode(&fe);
         assert_eq!(eg.to_bytes(), zero);
     }
+
+    #[test]
+    fn montgomery_representative_roundtrip() {
+        let mut csprng: OsRng = OsRng;
+        let mut found = 0;
+
+        for i in 0..256u32 {
+            let s: Scalar = Scalar::random(&mut csprng);
+            let p: MontgomeryPoint = (&constants::ED25519_BASEPOINT_TABLE * &s).to_montgomery();
+            let tweak = (i % 256) as u8;
+
+            if let Some(representative) = p.to_representative(tweak) {
+                found += 1;
+                assert_eq!(MontgomeryPoint::from_representative(&representative), p);
+                assert_eq!(representative[31] & 0x80, tweak & 0x80);
+            }
+        }
+
+        // About half of all points have a representative
+        assert!(found > 64 && found < 192);
+    }
+
+    #[test]
+    #[cfg(feature = "std")] // Vec
+    fn montgomery_representatives_of_a_point_differ() {
+        let mut csprng: OsRng = OsRng;
+
+        loop {
+            let s: Scalar = Scalar::random(&mut csprng);
+            let p: MontgomeryPoint = (&constants::ED25519_BASEPOINT_TABLE * &s).to_montgomery();
+            let representatives: std::vec::Vec<[u8; 32]> = (0u8..4).filter_map(|tweak| p.to_representative(tweak)).collect();
+            if representatives.is_empty() {
+                continue;
+            }
+
+            assert_eq!(representatives.len(), 4);
+            for (i, r) in representatives.iter().enumerate() {
+                assert_eq!(MontgomeryPoint::from_representative(r), p);
+                assert!(representatives[..i].iter().all(|other| other != r));
+            }
+            break;
+        }
+    }
+
+    #[test]
+    fn montgomery_representative_rejects_twist() {
+        let one = FieldElement::one();
+        let two = MontgomeryPoint((&one+&one).to_bytes());
+
+        for tweak in 0..4 {
+            assert!(two.to_representative(tweak).is_none());
+        }
+        assert_eq!(MontgomeryPoint::default().to_representative(2), Some([0u8; 32]));
+    }
 }
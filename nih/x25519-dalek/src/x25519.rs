@@ -87,6 +87,16 @@ impl EphemeralSecret {
 
         EphemeralSecret(clamp_scalar(bytes))
     }
+
+    /// Generate an x25519 [`EphemeralSecret`] key, returning an error instead
+    /// of panicking if the `csprng` fails.
+    pub fn try_new<T: RngCore + CryptoRng>(mut csprng: T) -> Result<Self, rand_core::Error> {
+        let mut bytes = [0u8; 32];
+
+        csprng.try_fill_bytes(&mut bytes)?;
+
+        Ok(EphemeralSecret(clamp_scalar(bytes)))
+    }
 }
 
 impl<'a> From<&'a EphemeralSecret> for PublicKey {
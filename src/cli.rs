@@ -1,9 +1,39 @@
 use anyhow::Result;
 use tracing::metadata::LevelFilter;
+use zorn_core::{header::HeaderError, identity_file::IdentityFileError};
 
 const BINNAME: &str = clap::crate_name!();
 
+mod keys;
+#[cfg(target_os = "linux")]
+mod memfd;
+mod output;
+
+// clap already exits with 2 on usage errors
+const EXIT_FAILURE: u8 = 1;
+const EXIT_HEADER: u8 = 3;
+const EXIT_IDENTITY: u8 = 4;
+const EXIT_AUTHENTICATION: u8 = 5;
+const EXIT_TRUNCATED: u8 = 6;
+const EXIT_TRAILING_DATA: u8 = 7;
+const EXIT_RNG: u8 = 8;
+const EXIT_PADDING: u8 = 9;
+
+const EXIT_STATUS: &str = indoc::indoc!{"
+    EXIT STATUS:
+        1    any other failure, including I/O errors
+        2    invalid command line arguments
+        3    the input is not a supported zorn message
+        4    an identity, secret key or identity file is invalid
+        5    the message failed to authenticate
+        6    the message is truncated
+        7    data follows the end of the message
+        8    the random number generator failed
+        9    the message's padding is malformed
+"};
+
 #[derive(Debug, clap::Parser)]
+#[clap(after_help = EXIT_STATUS)]
 struct Cli {
     #[clap(subcommand)]
     cmd: Cmd,
@@ -25,7 +55,11 @@ command_builder::build_commands!(
             Ok(())
         }
     },
-    encrypt
+    encrypt,
+    decrypt,
+    inspect,
+    keygen,
+    verify,
 );
 
 pub(crate) fn run() -> Result<()> {
@@ -35,6 +69,36 @@ pub(crate) fn run() -> Result<()> {
     dispatch_cmd(cli.cmd)
 }
 
+fn identity_file_exit_code(e: &IdentityFileError) -> u8 {
+    match e {
+        IdentityFileError::Io(_) => EXIT_FAILURE,
+        _ => EXIT_IDENTITY,
+    }
+}
+
+pub(crate) fn exit_code(e: &anyhow::Error) -> u8 {
+    for cause in e.chain() {
+        if let Some(e) = cause.downcast_ref::<zorn_core::Error>() {
+            return match e {
+                zorn_core::Error::Header(HeaderError::Io(_)) | zorn_core::Error::Io(_) | zorn_core::Error::Chunk(_) => EXIT_FAILURE,
+                zorn_core::Error::Header(_) => EXIT_HEADER,
+                zorn_core::Error::IdentityFile(e) => identity_file_exit_code(e),
+                zorn_core::Error::Identity(_) | zorn_core::Error::SecretKey(_) | zorn_core::Error::MasterSecret(_)
+                    | zorn_core::Error::KeyExchange(_) => EXIT_IDENTITY,
+                zorn_core::Error::Authentication { .. } => EXIT_AUTHENTICATION,
+                zorn_core::Error::Truncated { .. } => EXIT_TRUNCATED,
+                zorn_core::Error::TrailingData => EXIT_TRAILING_DATA,
+                zorn_core::Error::Rng(_) => EXIT_RNG,
+                zorn_core::Error::Padding { .. } => EXIT_PADDING,
+            };
+        }
+        if let Some(e) = cause.downcast_ref::<IdentityFileError>() {
+            return identity_file_exit_code(e);
+        }
+    }
+    EXIT_FAILURE
+}
+
 fn init_tracing(_: &Cli) {
     use tracing_subscriber::prelude::*;
     use tracing_subscriber::{fmt, EnvFilter, registry};
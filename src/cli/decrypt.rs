@@ -0,0 +1,181 @@
+#[cfg(target_os = "linux")]
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use tracing::debug;
+use anyhow::{Context, Result};
+use rand_core::OsRng;
+use zorn_core::{identity::{ZornIdentity, ZornIdentitySecret}, seekable::SeekableDecryptor, spool::Spool};
+#[cfg(feature = "parallel")]
+use zorn_core::parallel::{ParallelDecryptor, DEFAULT_CHUNKS_IN_FLIGHT};
+#[cfg(not(feature = "parallel"))]
+use zorn_core::stream::Decryptor;
+
+use super::keys::read_identity_file;
+use super::output::Output;
+
+#[derive(Debug, clap::Args)]
+pub(super) struct CmdArgs {
+    /// The sender's identity
+    #[clap(long, value_name = "IDENTITY", value_parser = ZornIdentity::from_str_strict)]
+    from: ZornIdentity,
+
+    /// The identity file containing the recipient's secret keys
+    #[clap(short, long, value_name = "SECRET-FILE", value_parser)]
+    identity: PathBuf,
+
+    /// Write the decrypted message to OUTPUT instead of stdout
+    #[clap(short, long, value_parser)]
+    output: Option<PathBuf>,
+
+    /// Authenticate the whole message before writing any output, holding it in an encrypted temporary file
+    #[clap(long, conflicts_with_all = &["offset", "length"])]
+    buffered: bool,
+
+    /// Authenticate the whole message into sealed memory, then run CMD on it as stdin in place of zorn
+    #[cfg(target_os = "linux")]
+    #[clap(long, requires = "command", conflicts_with_all = &["output", "buffered", "offset", "length"])]
+    exec: bool,
+
+    /// Only decrypt the plaintext starting at OFFSET, authenticating just the chunks covering it
+    #[clap(long, value_parser, requires = "input")]
+    offset: Option<u64>,
+
+    /// Only decrypt up to LENGTH octets of plaintext
+    #[clap(long, value_parser, requires = "input")]
+    length: Option<u64>,
+
+    /// Decrypt a uniform message, as written by encrypt --uniform
+    #[clap(long)]
+    uniform: bool,
+
+    /// The message to decrypt, read from stdin if absent
+    #[clap(value_parser)]
+    input: Option<PathBuf>,
+
+    /// The command to run with --exec and its arguments
+    #[cfg(target_os = "linux")]
+    #[clap(last = true, value_name = "CMD", requires = "exec", value_parser)]
+    command: Vec<OsString>,
+}
+
+impl CmdArgs {
+    #[tracing::instrument]
+    pub(super) fn run(&self) -> Result<()> {
+        let recipient_secrets = read_identity_file(&self.identity)?;
+
+        if self.offset.is_some() || self.length.is_some() {
+            let path = self.input.as_deref().expect("clap requires an input with --offset or --length");
+            return self.decrypt_range(path, &recipient_secrets);
+        }
+
+        let input: Box<dyn Read> = match &self.input {
+            Some(path) => Box::new(File::open(path).with_context(|| format!("failed to open {}", path.display()))?),
+            None => Box::new(io::stdin().lock()),
+        };
+
+        #[cfg(feature = "parallel")]
+        let decryptor = match self.uniform {
+            true => ParallelDecryptor::uniform_with_identities(input, &recipient_secrets, &self.from, DEFAULT_CHUNKS_IN_FLIGHT),
+            false => ParallelDecryptor::with_identities(input, &recipient_secrets, &self.from, DEFAULT_CHUNKS_IN_FLIGHT),
+        };
+        #[cfg(not(feature = "parallel"))]
+        let decryptor = match self.uniform {
+            true => Decryptor::uniform_with_identities(input, &recipient_secrets, &self.from),
+            false => Decryptor::with_identities(input, &recipient_secrets, &self.from),
+        };
+        let mut decryptor = decryptor.context("failed to start decrypting the message")?;
+
+        if self.buffered {
+            return self.decrypt_buffered(decryptor);
+        }
+        #[cfg(target_os = "linux")]
+        if self.exec {
+            return self.decrypt_exec(decryptor);
+        }
+
+        let mut output = self.open_output()?;
+
+        // Pass on every authenticated chunk as soon as it is available
+        loop {
+            let chunk = decryptor.fill_buf().map_err(zorn_core::Error::from).context("failed to decrypt the message")?;
+            if chunk.is_empty() {
+                break;
+            }
+            let len = chunk.len();
+            output.write_all(chunk).and_then(|_| output.flush()).context("failed to write the decrypted message")?;
+            decryptor.consume(len);
+        }
+        output.finish()?;
+
+        debug!(len = decryptor.authenticated_bytes(), "Decrypted message");
+        Ok(())
+    }
+
+    fn open_output(&self) -> Result<Output> {
+        Output::open(self.output.as_deref())
+    }
+
+    // The spool file is unlinked from the start, so it disappears with the
+    // process whether or not the message turns out to be authentic
+    fn decrypt_buffered<R: Read>(&self, mut decryptor: R) -> Result<()> {
+        let file = tempfile::tempfile().context("failed to create a temporary file")?;
+        let mut spool = Spool::new(file, OsRng).context("failed to start buffering the message")?;
+        let len = io::copy(&mut decryptor, &mut spool)
+            .map_err(zorn_core::Error::from)
+            .context("failed to decrypt the message")?;
+        let mut spooled = spool.finish().context("failed to write the temporary file")?;
+
+        let mut output = self.open_output()?;
+        io::copy(&mut spooled, &mut output).context("failed to write the decrypted message")?;
+        output.finish()?;
+
+        debug!(len, "Decrypted buffered message");
+        Ok(())
+    }
+
+    // CMD only ever starts on a complete and authentic message, which it reads
+    // from a memfd that nobody can change anymore. It replaces zorn, so its
+    // exit status is the one the caller sees.
+    #[cfg(target_os = "linux")]
+    fn decrypt_exec<R: Read>(&self, mut decryptor: R) -> Result<()> {
+        use std::os::unix::process::CommandExt;
+
+        let mut memfd = super::memfd::create(c"zorn-decrypted").context("failed to create a memfd")?;
+        let len = io::copy(&mut decryptor, &mut memfd)
+            .map_err(zorn_core::Error::from)
+            .context("failed to decrypt the message")?;
+        super::memfd::seal(&memfd).context("failed to seal the memfd")?;
+        memfd.seek(SeekFrom::Start(0)).context("failed to rewind the memfd")?;
+        debug!(len, "Decrypted message for exec");
+
+        let (program, args) = self.command.split_first().expect("clap requires a command with --exec");
+        let e = std::process::Command::new(program).args(args).stdin(memfd).exec();
+        Err(e).with_context(|| format!("failed to run {}", program.to_string_lossy()))
+    }
+
+    fn decrypt_range(&self, path: &Path, recipient_secrets: &[ZornIdentitySecret]) -> Result<()> {
+        let input = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        let decryptor = match self.uniform {
+            true => SeekableDecryptor::uniform_with_identities(input, recipient_secrets, &self.from),
+            false => SeekableDecryptor::with_identities(input, recipient_secrets, &self.from),
+        };
+        let mut decryptor = decryptor.context("failed to start decrypting the message")?;
+        let offset = self.offset.unwrap_or(0);
+        decryptor.seek(SeekFrom::Start(offset)).context("failed to seek in the message")?;
+
+        let mut output = self.open_output()?;
+        let len = io::copy(&mut decryptor.take(self.length.unwrap_or(u64::MAX)), &mut output)
+            .map_err(zorn_core::Error::from)
+            .context("failed to decrypt the message")?;
+        output.finish()?;
+
+        debug!(offset, len, "Decrypted range");
+        Ok(())
+    }
+}
+
+pub(super) const ABOUT: &str = indoc::indoc!{"
+    Decrypt a message, only ever releasing authenticated data
+"};
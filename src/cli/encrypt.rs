@@ -1,18 +1,116 @@
-use tracing::debug;
-use anyhow::Result;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use tracing::{debug, warn};
+use anyhow::{Context, Result};
+use rand_chacha::ChaCha20Rng;
+use rand_core::{CryptoRng, OsRng, RngCore, SeedableRng};
+use zorn_core::identity::{ZornIdentity, ZornIdentitySecret};
+use zorn_core::padding::Padding;
+#[cfg(feature = "parallel")]
+use zorn_core::parallel::{ParallelEncryptor, DEFAULT_CHUNKS_IN_FLIGHT};
+#[cfg(not(feature = "parallel"))]
+use zorn_core::stream::Encryptor;
+
+use super::keys::read_sender_identity;
+use super::output::Output;
 
 #[derive(Debug, clap::Args)]
 pub(super) struct CmdArgs {
+    /// The recipient's identity
+    #[clap(long, value_name = "IDENTITY", value_parser = ZornIdentity::from_str_strict)]
+    to: ZornIdentity,
+
+    /// The identity file containing the sender's secret key
+    #[clap(short, long, value_name = "SECRET-FILE", value_parser)]
+    identity: PathBuf,
+
+    /// Write the encrypted message to OUTPUT instead of stdout
+    #[clap(short, long, value_parser)]
+    output: Option<PathBuf>,
+
+    /// Pad the message to hide its length, either to the next Padmé bucket,
+    /// which leaks at most O(log log n) bits of it at an overhead below 12%,
+    /// or to exactly SIZE bytes, with an optional K, M or G suffix. Padded
+    /// messages are v2, which versions of zorn without padding can't decrypt
+    #[clap(long, value_name = "padme|SIZE", value_parser = parse_padding)]
+    pad: Option<Padding>,
+
+    /// Write a uniform message without a header, indistinguishable from
+    /// random bytes, which has to be decrypted with --uniform as well
+    #[clap(long)]
+    uniform: bool,
+
+    /// The message to encrypt, read from stdin if absent
+    #[clap(value_parser)]
+    input: Option<PathBuf>,
+
+    /// INSECURE, FOR TESTS ONLY: derive the ephemeral key from SEED, so that
+    /// the output is reproducible and anyone knowing SEED can decrypt it
+    #[clap(long, value_name = "SEED", value_parser, hide = true)]
+    insecure_test_seed: Option<u64>,
 }
 
 impl CmdArgs {
     #[tracing::instrument]
     pub(super) fn run(&self) -> Result<()> {
-        debug!("Encryption placeholder");
+        let sender_secret = read_sender_identity(&self.identity)?;
+
+        let input: Box<dyn Read> = match &self.input {
+            Some(path) => Box::new(File::open(path).with_context(|| format!("failed to open {}", path.display()))?),
+            None => Box::new(io::stdin().lock()),
+        };
+        let output = Output::open(self.output.as_deref())?;
+
+        match self.insecure_test_seed {
+            Some(seed) => {
+                warn!("Encrypting with a seeded ephemeral key, the output is not confidential");
+                self.encrypt(ChaCha20Rng::seed_from_u64(seed), &sender_secret, input, output)
+            }
+            None => self.encrypt(OsRng, &sender_secret, input, output),
+        }
+    }
+
+    fn encrypt<T: RngCore + CryptoRng>(&self, csprng: T, sender_secret: &ZornIdentitySecret, mut input: Box<dyn Read>, output: Output) -> Result<()> {
+        #[cfg(feature = "parallel")]
+        let encryptor = match self.uniform {
+            true => ParallelEncryptor::uniform_with_rng(output, csprng, sender_secret, &self.to, DEFAULT_CHUNKS_IN_FLIGHT),
+            false => ParallelEncryptor::with_rng(output, csprng, sender_secret, &self.to, DEFAULT_CHUNKS_IN_FLIGHT),
+        };
+        #[cfg(not(feature = "parallel"))]
+        let encryptor = match self.uniform {
+            true => Encryptor::uniform_with_rng(output, csprng, sender_secret, &self.to),
+            false => Encryptor::with_rng(output, csprng, sender_secret, &self.to),
+        };
+        let mut encryptor = encryptor.context("failed to write the header")?;
+        if let Some(padding) = self.pad {
+            encryptor = encryptor.with_padding(padding);
+        }
+        let len = io::copy(&mut input, &mut encryptor).context("failed to encrypt the message")?;
+        encryptor.finish().context("failed to write the final chunk")?.finish()?;
+
+        debug!(len, "Encrypted message");
         Ok(())
     }
 }
 
+fn parse_padding(s: &str) -> Result<Padding> {
+    if s == "padme" {
+        return Ok(Padding::Padme);
+    }
+    let (digits, shift) = match s.as_bytes().last() {
+        Some(b'K') => (&s[..s.len() - 1], 10),
+        Some(b'M') => (&s[..s.len() - 1], 20),
+        Some(b'G') => (&s[..s.len() - 1], 30),
+        _ => (s, 0),
+    };
+    let size: u64 = digits.parse().with_context(|| format!("expected padme or a size, not {s:?}"))?;
+    let size = size.checked_mul(1u64 << shift).with_context(|| format!("{s} is too large"))?;
+    // Even an empty message needs room for the padding marker
+    anyhow::ensure!(size > 0, "padding to {s} leaves no room for the padding marker");
+    Ok(Padding::To(size))
+}
+
 pub(super) const ABOUT: &str = indoc::indoc!{"
     Encrypt a message
 "};
@@ -0,0 +1,102 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::PathBuf;
+use anyhow::{Context, Result};
+use zorn_core::header::{Header, VERSION_LINE_PREFIX};
+use zorn_core::identity::ZornIdentity;
+use zorn_core::stream::{plaintext_len, ENCRYPTED_CHUNK_SIZE};
+
+#[derive(Debug, clap::Args)]
+pub(super) struct CmdArgs {
+    /// Print a JSON object instead of a human readable summary
+    #[clap(long)]
+    json: bool,
+
+    /// The message to inspect, read from stdin if absent
+    #[clap(value_parser)]
+    input: Option<PathBuf>,
+}
+
+struct Layout {
+    version_line: String,
+    ephemeral_identity: ZornIdentity,
+    ciphertext_len: u64,
+    payload_len: u64,
+    chunks: u64,
+    plaintext_len: Option<u64>,
+}
+
+impl Layout {
+    fn read_from<R: Read>(mut reader: R, len: Option<u64>) -> Result<Self> {
+        let header = Header::read_from(&mut reader).map_err(zorn_core::Error::from).context("failed to read the header")?;
+        let header_len = header.encoded_len() as u64;
+        // Files report their length up front, anything else has to be read to the end
+        let payload_len = match len {
+            Some(len) => len.saturating_sub(header_len),
+            None => io::copy(&mut reader, &mut io::sink()).context("failed to read the message")?,
+        };
+
+        Ok(Layout {
+            version_line: format!("{}{}", String::from_utf8_lossy(VERSION_LINE_PREFIX), header.version.as_str()),
+            ephemeral_identity: ZornIdentity::from(header.ephemeral_identity.to_bytes()),
+            ciphertext_len: header_len + payload_len,
+            payload_len,
+            chunks: payload_len.div_ceil(ENCRYPTED_CHUNK_SIZE as u64),
+            plaintext_len: plaintext_len(payload_len),
+        })
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "version_line": self.version_line,
+            "ephemeral_identity": self.ephemeral_identity.to_string(),
+            "ciphertext_len": self.ciphertext_len,
+            "payload_len": self.payload_len,
+            "chunks": self.chunks,
+            "plaintext_len": self.plaintext_len,
+            "valid": self.plaintext_len.is_some(),
+        })
+    }
+
+    fn print(&self) {
+        println!("version line:        {}", self.version_line);
+        println!("ephemeral identity:  {}", self.ephemeral_identity.to_string());
+        println!("ciphertext length:   {} ({} following the header)", self.ciphertext_len, self.payload_len);
+        println!("chunks:              {}", self.chunks);
+        match self.plaintext_len {
+            Some(len) => {
+                println!("plaintext length:    {} (including any padding)", len);
+                println!("structurally valid:  yes");
+            }
+            None => {
+                println!("plaintext length:    unknown");
+                println!("structurally valid:  no, the payload does not end in a valid final chunk");
+            }
+        }
+    }
+}
+
+impl CmdArgs {
+    #[tracing::instrument]
+    pub(super) fn run(&self) -> Result<()> {
+        let layout = match &self.input {
+            Some(path) => {
+                let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+                let metadata = file.metadata().with_context(|| format!("failed to read the length of {}", path.display()))?;
+                Layout::read_from(BufReader::new(file), metadata.is_file().then_some(metadata.len()))?
+            }
+            None => Layout::read_from(io::stdin().lock(), None)?,
+        };
+
+        if self.json {
+            println!("{}", layout.to_json());
+        } else {
+            layout.print();
+        }
+        Ok(())
+    }
+}
+
+pub(super) const ABOUT: &str = indoc::indoc!{"
+    Show the header and chunk layout of a message without any keys
+"};
@@ -0,0 +1,112 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use anyhow::{Context, Result};
+use rand_core::OsRng;
+use zorn_core::identity::{ZornIdentity, ZornIdentitySecret, ZornMasterSecret};
+use zorn_core::identity_file::{self, IdentityFile};
+
+#[derive(Debug, clap::Args)]
+pub(super) struct CmdArgs {
+    /// Write the identity file to OUTPUT instead of stdout
+    #[clap(short, long, value_parser)]
+    output: Option<PathBuf>,
+
+    /// Overwrite OUTPUT if it already exists
+    #[clap(short, long, requires = "output")]
+    force: bool,
+
+    /// Generate a master secret for deriving identities instead of an identity
+    #[clap(long, conflicts_with = "from-master")]
+    master: bool,
+
+    /// Deterministically derive the identity from the master secret in MASTER-FILE
+    #[clap(long, value_name = "MASTER-FILE", value_parser)]
+    from_master: Option<PathBuf>,
+
+    /// Derive a separate identity for LABEL from the master secret
+    #[clap(long, requires = "from-master", value_parser)]
+    label: Option<String>,
+}
+
+fn create_secret_file(path: &Path, force: bool) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if force {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+
+    #[cfg(unix)] {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        let file = options.open(path)?;
+        // The mode only applies to newly created files
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+        Ok(file)
+    }
+
+    #[cfg(not(unix))]
+    options.open(path)
+}
+
+impl CmdArgs {
+    #[tracing::instrument]
+    pub(super) fn run(&self) -> Result<()> {
+        let created = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+
+        if self.master {
+            let master_secret = ZornMasterSecret::try_new(OsRng)
+                .map_err(zorn_core::Error::from)
+                .context("failed to gather randomness for the master secret")?;
+            return self.write_output(|w| identity_file::write_master_secret(w, &master_secret, Some(&created)), None);
+        }
+
+        let secret = match &self.from_master {
+            Some(path) => {
+                let file = File::open(path).with_context(|| format!("failed to open master secret file {}", path.display()))?;
+                let master_secret = identity_file::read_master_secret(BufReader::new(file))
+                    .with_context(|| format!("failed to read master secret file {}", path.display()))?;
+                ZornIdentitySecret::derive(&master_secret, self.label.as_deref())
+            }
+            None => ZornIdentitySecret::try_new(OsRng)
+                .map_err(zorn_core::Error::from)
+                .context("failed to gather randomness for the secret key")?,
+        };
+        let identity = ZornIdentity::from(&secret);
+
+        let identities = IdentityFile::new(vec![secret]);
+        self.write_output(|w| identities.write_to(w, Some(&created)), Some(&identity))
+    }
+
+    fn write_output(&self, write: impl FnOnce(&mut dyn Write) -> io::Result<()>, identity: Option<&ZornIdentity>) -> Result<()> {
+        match &self.output {
+            Some(path) => {
+                let mut file = create_secret_file(path, self.force)
+                    .with_context(|| format!("failed to create {}", path.display()))?;
+                write(&mut file)
+                    .and_then(|_| file.sync_all())
+                    .with_context(|| format!("failed to write {}", path.display()))?;
+                if let Some(identity) = identity {
+                    println!("{}", identity.to_string());
+                }
+            }
+            None => {
+                let mut stdout = io::stdout().lock();
+                write(&mut stdout)
+                    .and_then(|_| stdout.flush())
+                    .context("failed to write the secret")?;
+                if let Some(identity) = identity {
+                    eprintln!("Public identity: {}", identity.to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(super) const ABOUT: &str = indoc::indoc!{"
+    Generate a new identity file and print its public identity
+"};
@@ -0,0 +1,20 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use anyhow::{Context, Result};
+use zorn_core::{identity::ZornIdentitySecret, identity_file::IdentityFile};
+
+pub(super) fn read_identity_file(path: &Path) -> Result<Vec<ZornIdentitySecret>> {
+    let file = File::open(path)
+        .with_context(|| format!("failed to open identity file {}", path.display()))?;
+    let identities = IdentityFile::read_from(BufReader::new(file))
+        .with_context(|| format!("failed to read identity file {}", path.display()))?;
+    Ok(identities.into_identities())
+}
+
+pub(super) fn read_sender_identity(path: &Path) -> Result<ZornIdentitySecret> {
+    let mut identities = read_identity_file(path)?;
+    anyhow::ensure!(identities.len() == 1,
+        "identity file {} contains {} secret keys, but the sender needs to be unambiguous", path.display(), identities.len());
+    Ok(identities.remove(0))
+}
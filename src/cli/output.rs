@@ -0,0 +1,152 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use anyhow::{Context, Result};
+use tempfile::NamedTempFile;
+
+// Temporary files to remove if a termination signal arrives before they are
+// renamed into place
+static PENDING: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+#[cfg(unix)]
+fn install_signal_handler() -> io::Result<()> {
+    use std::sync::OnceLock;
+    use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+    use signal_hook::iterator::Signals;
+
+    static INSTALLED: OnceLock<()> = OnceLock::new();
+    if INSTALLED.get().is_some() {
+        return Ok(());
+    }
+
+    // Rust ignores SIGPIPE, so a broken pipe shows up as a write error and
+    // takes the same way out as any other failure
+    let mut signals = Signals::new([SIGHUP, SIGINT, SIGTERM])?;
+    std::thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            for path in PENDING.lock().unwrap_or_else(|e| e.into_inner()).drain(..) {
+                let _ = std::fs::remove_file(path);
+            }
+            let _ = signal_hook::low_level::emulate_default_handler(signal);
+        }
+    });
+    let _ = INSTALLED.set(());
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn install_signal_handler() -> io::Result<()> {
+    Ok(())
+}
+
+// An output file that only ever appears complete. Everything is written to a
+// sibling temporary file, created exclusively and only readable by its owner,
+// which replaces the target once the command has succeeded. Dropping it
+// without commit() removes the temporary file.
+pub(super) struct AtomicFile {
+    file: NamedTempFile,
+    _pending: Pending,
+    path: PathBuf,
+}
+
+// Keeps a temporary file registered for removal on a signal for as long as it
+// may exist at its own path
+struct Pending(PathBuf);
+
+impl Pending {
+    fn register(path: &Path) -> Self {
+        PENDING.lock().unwrap_or_else(|e| e.into_inner()).push(path.to_owned());
+        Pending(path.to_owned())
+    }
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        PENDING.lock().unwrap_or_else(|e| e.into_inner()).retain(|p| p != &self.0);
+    }
+}
+
+impl AtomicFile {
+    pub(super) fn create(path: &Path) -> io::Result<Self> {
+        install_signal_handler()?;
+
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let name = path.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "output path has no file name"))?;
+        let mut prefix = std::ffi::OsString::from(".");
+        prefix.push(name);
+        prefix.push(".");
+
+        let file = tempfile::Builder::new().prefix(&prefix).suffix(".tmp").tempfile_in(dir)?;
+        let pending = Pending::register(file.path());
+        Ok(AtomicFile { file, _pending: pending, path: path.to_owned() })
+    }
+
+    pub(super) fn commit(self) -> io::Result<()> {
+        let AtomicFile { mut file, _pending, path } = self;
+        file.flush()?;
+        file.as_file().sync_all()?;
+        file.persist(&path).map_err(|e| e.error)?;
+
+        // Make the rename itself durable
+        #[cfg(unix)]
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            File::open(dir)?.sync_all()?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+pub(super) enum Output {
+    Stdout(io::StdoutLock<'static>),
+    File(AtomicFile),
+}
+
+impl Output {
+    pub(super) fn open(path: Option<&Path>) -> Result<Self> {
+        Ok(match path {
+            Some(path) => Output::File(AtomicFile::create(path).with_context(|| format!("failed to create {}", path.display()))?),
+            None => Output::Stdout(io::stdout().lock()),
+        })
+    }
+
+    // Only an output that is finished ever shows up at its path
+    pub(super) fn finish(self) -> Result<()> {
+        match self {
+            Output::Stdout(mut stdout) => stdout.flush().context("failed to write to stdout"),
+            Output::File(file) => {
+                let path = file.path.clone();
+                file.commit().with_context(|| format!("failed to move the output into place at {}", path.display()))
+            }
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Stdout(stdout) => stdout.write(buf),
+            Output::File(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Stdout(stdout) => stdout.flush(),
+            Output::File(file) => file.flush(),
+        }
+    }
+}
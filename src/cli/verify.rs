@@ -0,0 +1,54 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use tracing::debug;
+use anyhow::{Context, Result};
+use zorn_core::identity::ZornIdentity;
+use zorn_core::stream::Verifier;
+
+use super::keys::read_identity_file;
+
+#[derive(Debug, clap::Args)]
+pub(super) struct CmdArgs {
+    /// The sender's identity
+    #[clap(long, value_name = "IDENTITY", value_parser = ZornIdentity::from_str_strict)]
+    from: ZornIdentity,
+
+    /// The identity file containing the recipient's secret keys
+    #[clap(short, long, value_name = "SECRET-FILE", value_parser)]
+    identity: PathBuf,
+
+    /// Verify a uniform message, as written by encrypt --uniform
+    #[clap(long)]
+    uniform: bool,
+
+    /// The message to verify, read from stdin if absent
+    #[clap(value_parser)]
+    input: Option<PathBuf>,
+}
+
+impl CmdArgs {
+    #[tracing::instrument]
+    pub(super) fn run(&self) -> Result<()> {
+        let recipient_secrets = read_identity_file(&self.identity)?;
+
+        let input: Box<dyn Read> = match &self.input {
+            Some(path) => Box::new(File::open(path).with_context(|| format!("failed to open {}", path.display()))?),
+            None => Box::new(io::stdin().lock()),
+        };
+        let verifier = match self.uniform {
+            true => Verifier::uniform_with_identities(input, &recipient_secrets, &self.from),
+            false => Verifier::with_identities(input, &recipient_secrets, &self.from),
+        };
+        let mut verifier = verifier.context("failed to start verifying the message")?;
+        let result = verifier.verify();
+        let len = result.with_context(|| format!("verification failed at chunk {}", verifier.verified_chunks()))?;
+
+        debug!(len, chunks = verifier.verified_chunks(), "Verified message");
+        Ok(())
+    }
+}
+
+pub(super) const ABOUT: &str = indoc::indoc!{"
+    Authenticate a whole message without writing any plaintext
+"};
@@ -0,0 +1,639 @@
+use std::path::{Path, PathBuf};
+use assert_cmd::Command;
+use predicates::prelude::*;
+use tempfile::TempDir;
+use zorn_core::identity::{ZornIdentity, ZornIdentitySecret};
+
+struct Party {
+    secret_file: PathBuf,
+    identity: String,
+}
+
+fn party(dir: &Path, name: &str, secret: [u8; 32]) -> Party {
+    let secret = ZornIdentitySecret::from(secret);
+    let secret_file = dir.join(name);
+    std::fs::write(&secret_file, format!("# {}\n{}\n", name, secret)).expect("Writing a test file should succeed, but");
+    Party { secret_file, identity: ZornIdentity::from(&secret).to_string() }
+}
+
+fn zorn() -> Command {
+    Command::cargo_bin("zorn").expect("The zorn binary should be built, but")
+}
+
+#[test]
+fn encrypt_writes_header_and_chunks() {
+    let dir = TempDir::new().expect("Creating a temporary directory should succeed, but");
+    let alice = party(dir.path(), "alice", [1u8; 32]);
+    let bob = party(dir.path(), "bob", [2u8; 32]);
+
+    let output = zorn().arg("encrypt")
+        .arg("--to").arg(&bob.identity)
+        .arg("--identity").arg(&alice.secret_file)
+        .write_stdin(vec![0u8; 70000])
+        .assert()
+        .success()
+        .get_output().stdout.clone();
+
+    assert!(output.starts_with(b"zorn-encryption.org/v1\n"));
+    assert_eq!(output.len(), 55 + 70000 + 2 * 32);
+}
+
+#[test]
+fn encrypt_rejects_invalid_recipient() {
+    let dir = TempDir::new().expect("Creating a temporary directory should succeed, but");
+    let alice = party(dir.path(), "alice", [1u8; 32]);
+
+    zorn().arg("encrypt")
+        .arg("--to").arg("zornv1-1gjfs6r7x5fmydhgrz9cnwrdkdnnvt3w7zhwya6dwvrp538qjmd3s04fc4w")
+        .arg("--identity").arg(&alice.secret_file)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid checksum"));
+}
+
+fn encrypt(from: &Party, to: &Party, plaintext: &[u8]) -> Vec<u8> {
+    zorn().arg("encrypt")
+        .arg("--to").arg(&to.identity)
+        .arg("--identity").arg(&from.secret_file)
+        .write_stdin(plaintext)
+        .assert()
+        .success()
+        .get_output().stdout.clone()
+}
+
+// The seed only stands in for the OS RNG, so the golden file pins down the
+// whole format, from the key exchange to the chunk encryption
+#[test]
+fn encrypt_with_test_seed_matches_golden_file() {
+    let dir = TempDir::new().expect("Creating a temporary directory should succeed, but");
+    let alice = party(dir.path(), "alice", [1u8; 32]);
+    let bob = party(dir.path(), "bob", [2u8; 32]);
+    let encrypt_seeded = |seed: &str| zorn().arg("encrypt")
+        .arg("--to").arg(&bob.identity)
+        .arg("--identity").arg(&alice.secret_file)
+        .arg("--insecure-test-seed").arg(seed)
+        .write_stdin(&b"golden ciphertext\n"[..])
+        .assert()
+        .success()
+        .get_output().stdout.clone();
+
+    assert_eq!(encrypt_seeded("7"), include_bytes!("golden/encrypt-seed-7.zorn"));
+    assert_ne!(encrypt_seeded("8"), include_bytes!("golden/encrypt-seed-7.zorn"));
+
+    zorn().arg("encrypt").arg("--help")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("insecure-test-seed").not());
+}
+
+#[test]
+fn decrypt_roundtrip() {
+    let dir = TempDir::new().expect("Creating a temporary directory should succeed, but");
+    let alice = party(dir.path(), "alice", [1u8; 32]);
+    let bob = party(dir.path(), "bob", [2u8; 32]);
+    let plaintext: Vec<u8> = (0..200000u32).map(|i| i as u8).collect();
+
+    zorn().arg("decrypt")
+        .arg("--from").arg(&alice.identity)
+        .arg("--identity").arg(&bob.secret_file)
+        .write_stdin(encrypt(&alice, &bob, &plaintext))
+        .assert()
+        .success()
+        .stdout(plaintext);
+}
+
+#[test]
+fn encrypt_pads_messages() {
+    let dir = TempDir::new().expect("Creating a temporary directory should succeed, but");
+    let alice = party(dir.path(), "alice", [1u8; 32]);
+    let bob = party(dir.path(), "bob", [2u8; 32]);
+    let encrypt_padded = |pad: &str, plaintext: &[u8]| zorn().arg("encrypt")
+        .arg("--to").arg(&bob.identity)
+        .arg("--identity").arg(&alice.secret_file)
+        .arg("--pad").arg(pad)
+        .write_stdin(plaintext)
+        .assert();
+
+    for (pad, padded_len) in [("padme", 1024), ("1K", 1024), ("70000", 70000)] {
+        let short = encrypt_padded(pad, &[1u8; 1000]).success().get_output().stdout.clone();
+        let long = encrypt_padded(pad, &[2u8; 1020]).success().get_output().stdout.clone();
+        assert_eq!(short.len(), long.len());
+        assert_eq!(short.len() as u64, 55 + zorn_core::stream::ciphertext_len(padded_len));
+        assert!(short.starts_with(b"zorn-encryption.org/v2\n"));
+
+        zorn().arg("decrypt")
+            .arg("--from").arg(&alice.identity)
+            .arg("--identity").arg(&bob.secret_file)
+            .write_stdin(short)
+            .assert()
+            .success()
+            .stdout(vec![1u8; 1000]);
+    }
+
+    encrypt_padded("1K", &[0u8; 1024])
+        .failure()
+        .stderr(predicate::str::contains("too long for the padding"));
+    encrypt_padded("lots", b"hello")
+        .code(2);
+    encrypt_padded("0K", b"")
+        .code(2)
+        .stderr(predicate::str::contains("no room for the padding marker"));
+}
+
+#[test]
+fn decrypt_tries_every_secret_key() {
+    let dir = TempDir::new().expect("Creating a temporary directory should succeed, but");
+    let alice = party(dir.path(), "alice", [1u8; 32]);
+    let bob = party(dir.path(), "bob", [2u8; 32]);
+    let mallory = party(dir.path(), "mallory", [3u8; 32]);
+
+    let keyring = dir.path().join("keyring");
+    let contents = [&mallory.secret_file, &bob.secret_file].map(|f| std::fs::read_to_string(f).expect("Reading a test file should succeed, but")).concat();
+    std::fs::write(&keyring, contents).expect("Writing a test file should succeed, but");
+
+    zorn().arg("decrypt")
+        .arg("--from").arg(&alice.identity)
+        .arg("--identity").arg(&keyring)
+        .write_stdin(encrypt(&alice, &bob, b"hello"))
+        .assert()
+        .success()
+        .stdout("hello");
+
+    zorn().arg("encrypt")
+        .arg("--to").arg(&bob.identity)
+        .arg("--identity").arg(&keyring)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("contains 2 secret keys"));
+}
+
+#[test]
+fn decrypt_reports_truncation() {
+    let dir = TempDir::new().expect("Creating a temporary directory should succeed, but");
+    let alice = party(dir.path(), "alice", [1u8; 32]);
+    let bob = party(dir.path(), "bob", [2u8; 32]);
+    let plaintext = vec![0x5au8; 200000];
+    let file = encrypt(&alice, &bob, &plaintext);
+
+    zorn().arg("decrypt")
+        .arg("--from").arg(&alice.identity)
+        .arg("--identity").arg(&bob.secret_file)
+        .write_stdin(&file[..55 + 2 * (65536 + 32)])
+        .assert()
+        .code(6)
+        .stdout(predicate::eq(&plaintext[..2 * 65536]))
+        .stderr(predicate::str::contains("truncated after 131072 authenticated bytes"));
+}
+
+#[test]
+fn decrypt_buffered_releases_nothing_before_the_end() {
+    let dir = TempDir::new().expect("Creating a temporary directory should succeed, but");
+    let alice = party(dir.path(), "alice", [1u8; 32]);
+    let bob = party(dir.path(), "bob", [2u8; 32]);
+    let plaintext: Vec<u8> = (0..200000u32).map(|i| i as u8).collect();
+    let file = encrypt(&alice, &bob, &plaintext);
+    let output = dir.path().join("output");
+    let decrypt_buffered = || {
+        let mut cmd = zorn();
+        cmd.arg("decrypt").arg("--buffered").arg("--from").arg(&alice.identity).arg("--identity").arg(&bob.secret_file);
+        cmd
+    };
+
+    decrypt_buffered()
+        .write_stdin(file.clone())
+        .assert()
+        .success()
+        .stdout(plaintext);
+
+    decrypt_buffered()
+        .write_stdin(&file[..55 + 2 * (65536 + 32)])
+        .assert()
+        .code(6)
+        .stdout(predicate::str::is_empty());
+
+    decrypt_buffered()
+        .arg("--output").arg(&output)
+        .write_stdin(&file[..file.len() - 1])
+        .assert()
+        .code(5);
+    assert!(!output.exists());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn decrypt_exec_runs_command_on_sealed_plaintext() {
+    let dir = TempDir::new().expect("Creating a temporary directory should succeed, but");
+    let alice = party(dir.path(), "alice", [1u8; 32]);
+    let bob = party(dir.path(), "bob", [2u8; 32]);
+    let file = encrypt(&alice, &bob, &vec![b'a'; 200000]);
+    let marker = dir.path().join("marker");
+    let decrypt_exec = |input: &[u8], script: &str| zorn().arg("decrypt")
+        .arg("--from").arg(&alice.identity)
+        .arg("--identity").arg(&bob.secret_file)
+        .arg("--exec").arg("--").arg("sh").arg("-c").arg(script)
+        .write_stdin(input)
+        .assert();
+
+    // The command's exit status replaces zorn's, and stdin cannot be written to
+    decrypt_exec(&file, "wc -c; printf x >&0 2>/dev/null && echo writable || echo sealed; exit 42")
+        .code(42)
+        .stdout("200000\nsealed\n");
+
+    decrypt_exec(&file[..55 + 2 * (65536 + 32)], &format!("touch {}", marker.display()))
+        .code(6)
+        .stdout(predicate::str::is_empty());
+    assert!(!marker.exists());
+}
+
+#[test]
+fn output_files_only_appear_complete() {
+    let dir = TempDir::new().expect("Creating a temporary directory should succeed, but");
+    let alice = party(dir.path(), "alice", [1u8; 32]);
+    let bob = party(dir.path(), "bob", [2u8; 32]);
+    let plaintext = vec![0x5au8; 200000];
+    let file = encrypt(&alice, &bob, &plaintext);
+    let out_dir = dir.path().join("out");
+    std::fs::create_dir(&out_dir).expect("Creating a directory should succeed, but");
+    let output = out_dir.join("output");
+    let decrypt = || {
+        let mut cmd = zorn();
+        cmd.arg("decrypt").arg("--from").arg(&alice.identity).arg("--identity").arg(&bob.secret_file).arg("--output").arg(&output);
+        cmd
+    };
+
+    // Streaming decryption has written two chunks by the time the truncation shows
+    decrypt()
+        .write_stdin(&file[..55 + 2 * (65536 + 32)])
+        .assert()
+        .code(6);
+    assert_eq!(std::fs::read_dir(&out_dir).expect("Listing a directory should succeed, but").count(), 0);
+
+    decrypt()
+        .write_stdin(file.clone())
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&output).expect("Reading the output should succeed, but"), plaintext);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(std::fs::metadata(&output).expect("Reading metadata should succeed, but").permissions().mode() & 0o777, 0o600);
+    }
+
+    // A failure leaves an earlier output alone
+    decrypt()
+        .write_stdin(&file[..file.len() - 1])
+        .assert()
+        .code(5);
+    assert_eq!(std::fs::read(&output).expect("Reading the output should succeed, but"), plaintext);
+    assert_eq!(std::fs::read_dir(&out_dir).expect("Listing a directory should succeed, but").count(), 1);
+}
+
+#[cfg(unix)]
+#[test]
+fn output_file_is_removed_on_sigint() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let dir = TempDir::new().expect("Creating a temporary directory should succeed, but");
+    let alice = party(dir.path(), "alice", [1u8; 32]);
+    let bob = party(dir.path(), "bob", [2u8; 32]);
+    let out_dir = dir.path().join("out");
+    std::fs::create_dir(&out_dir).expect("Creating a directory should succeed, but");
+
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("zorn"))
+        .arg("encrypt")
+        .arg("--to").arg(&bob.identity)
+        .arg("--identity").arg(&alice.secret_file)
+        .arg("--output").arg(out_dir.join("output"))
+        .stdin(Stdio::piped())
+        .spawn()
+        .expect("Spawning zorn should succeed, but");
+    let mut stdin = child.stdin.take().expect("Stdin should be piped, but");
+    stdin.write_all(&[0x5au8; 200000]).expect("Writing to zorn should succeed, but");
+
+    // Keep stdin open, so that zorn is still waiting for more input when the signal arrives
+    let start = std::time::Instant::now();
+    while std::fs::read_dir(&out_dir).expect("Listing a directory should succeed, but").count() == 0 {
+        assert!(start.elapsed() < std::time::Duration::from_secs(10), "zorn never created its temporary file");
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    let status = std::process::Command::new("kill").arg("-INT").arg(child.id().to_string()).status().expect("Running kill should succeed, but");
+    assert!(status.success());
+
+    let status = child.wait().expect("Waiting for zorn should succeed, but");
+    drop(stdin);
+    assert!(!status.success());
+    assert_eq!(std::fs::read_dir(&out_dir).expect("Listing a directory should succeed, but").count(), 0);
+}
+
+#[test]
+fn uniform_messages_roundtrip() {
+    let dir = TempDir::new().expect("Creating a temporary directory should succeed, but");
+    let alice = party(dir.path(), "alice", [1u8; 32]);
+    let bob = party(dir.path(), "bob", [2u8; 32]);
+    let plaintext: Vec<u8> = (0..200000).map(|i| (i % 251) as u8).collect();
+
+    let file = zorn().arg("encrypt")
+        .arg("--to").arg(&bob.identity)
+        .arg("--identity").arg(&alice.secret_file)
+        .arg("--uniform")
+        .write_stdin(plaintext.clone())
+        .assert()
+        .success()
+        .get_output().stdout.clone();
+    assert!(!file.starts_with(b"zorn-encryption.org/"));
+    assert_eq!(file.len() as u64, 32 + zorn_core::stream::ciphertext_len(200000));
+    let message = dir.path().join("message.zorn");
+    std::fs::write(&message, &file).expect("Writing a test file should succeed, but");
+
+    zorn().arg("decrypt")
+        .arg("--from").arg(&alice.identity)
+        .arg("--identity").arg(&bob.secret_file)
+        .arg("--uniform")
+        .write_stdin(file.clone())
+        .assert()
+        .success()
+        .stdout(plaintext.clone());
+
+    zorn().arg("decrypt")
+        .arg("--from").arg(&alice.identity)
+        .arg("--identity").arg(&bob.secret_file)
+        .arg("--uniform")
+        .arg("--offset").arg("70000")
+        .arg("--length").arg("100")
+        .arg(&message)
+        .assert()
+        .success()
+        .stdout(predicate::eq(&plaintext[70000..70100]));
+
+    zorn().arg("verify")
+        .arg("--from").arg(&alice.identity)
+        .arg("--identity").arg(&bob.secret_file)
+        .arg("--uniform")
+        .arg(&message)
+        .assert()
+        .success();
+
+    zorn().arg("decrypt")
+        .arg("--from").arg(&alice.identity)
+        .arg("--identity").arg(&bob.secret_file)
+        .write_stdin(file)
+        .assert()
+        .code(3);
+
+    zorn().arg("decrypt")
+        .arg("--from").arg(&alice.identity)
+        .arg("--identity").arg(&bob.secret_file)
+        .arg("--uniform")
+        .write_stdin(encrypt(&alice, &bob, b"hello"))
+        .assert()
+        .code(5);
+}
+
+#[test]
+fn decrypt_reports_authentication_failure() {
+    let dir = TempDir::new().expect("Creating a temporary directory should succeed, but");
+    let alice = party(dir.path(), "alice", [1u8; 32]);
+    let bob = party(dir.path(), "bob", [2u8; 32]);
+    let mallory = party(dir.path(), "mallory", [3u8; 32]);
+
+    zorn().arg("decrypt")
+        .arg("--from").arg(&alice.identity)
+        .arg("--identity").arg(&bob.secret_file)
+        .write_stdin(encrypt(&mallory, &bob, b"hello"))
+        .assert()
+        .code(5)
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains("authentication failed for chunk 0"));
+}
+
+#[test]
+fn decrypt_exit_codes() {
+    let dir = TempDir::new().expect("Creating a temporary directory should succeed, but");
+    let alice = party(dir.path(), "alice", [1u8; 32]);
+    let bob = party(dir.path(), "bob", [2u8; 32]);
+
+    zorn().arg("decrypt")
+        .arg("--from").arg(&alice.identity)
+        .arg("--identity").arg(&bob.secret_file)
+        .write_stdin("age-encryption.org/v1\n")
+        .assert()
+        .code(3);
+
+    let mut file = encrypt(&alice, &bob, &vec![0u8; 65536]);
+    file.push(0);
+    zorn().arg("decrypt")
+        .arg("--from").arg(&alice.identity)
+        .arg("--identity").arg(&bob.secret_file)
+        .write_stdin(file)
+        .assert()
+        .code(7);
+
+    std::fs::write(&bob.secret_file, "not a secret key\n").expect("Writing a test file should succeed, but");
+    zorn().arg("decrypt")
+        .arg("--from").arg(&alice.identity)
+        .arg("--identity").arg(&bob.secret_file)
+        .write_stdin(encrypt(&alice, &alice, b"hello"))
+        .assert()
+        .code(4);
+
+    zorn().arg("decrypt")
+        .arg("--from").arg(&alice.identity)
+        .arg("--identity").arg(dir.path().join("missing"))
+        .assert()
+        .code(1);
+}
+
+#[test]
+fn decrypt_range() {
+    let dir = TempDir::new().expect("Creating a temporary directory should succeed, but");
+    let alice = party(dir.path(), "alice", [1u8; 32]);
+    let bob = party(dir.path(), "bob", [2u8; 32]);
+    let plaintext: Vec<u8> = (0..200000).map(|i| (i % 251) as u8).collect();
+    let mut file = encrypt(&alice, &bob, &plaintext);
+    // Only the chunks covering the requested range are authenticated
+    file[55] ^= 1;
+    let message = dir.path().join("message.zorn");
+    std::fs::write(&message, file).expect("Writing a test file should succeed, but");
+
+    zorn().arg("decrypt")
+        .arg("--from").arg(&alice.identity)
+        .arg("--identity").arg(&bob.secret_file)
+        .arg("--offset").arg("70000")
+        .arg("--length").arg("100000")
+        .arg(&message)
+        .assert()
+        .success()
+        .stdout(predicate::eq(&plaintext[70000..170000]));
+
+    zorn().arg("decrypt")
+        .arg("--from").arg(&alice.identity)
+        .arg("--identity").arg(&bob.secret_file)
+        .arg("--offset").arg("199990")
+        .arg(&message)
+        .assert()
+        .success()
+        .stdout(predicate::eq(&plaintext[199990..]));
+
+    zorn().arg("decrypt")
+        .arg("--from").arg(&alice.identity)
+        .arg("--identity").arg(&bob.secret_file)
+        .arg("--length").arg("10")
+        .arg(&message)
+        .assert()
+        .code(5)
+        .stdout(predicate::str::is_empty());
+}
+
+#[test]
+fn inspect_reports_layout() {
+    let dir = TempDir::new().expect("Creating a temporary directory should succeed, but");
+    let alice = party(dir.path(), "alice", [1u8; 32]);
+    let bob = party(dir.path(), "bob", [2u8; 32]);
+    let message = dir.path().join("message.zorn");
+    let ciphertext = encrypt(&alice, &bob, &vec![0u8; 70000]);
+    std::fs::write(&message, &ciphertext).expect("Writing a test file should succeed, but");
+    let ephemeral_identity = ZornIdentity::from(<[u8; 32]>::try_from(&ciphertext[23..55]).expect("An ephemeral identity is 32 octets, but")).to_string();
+
+    zorn().arg("inspect").arg(&message)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("zorn-encryption.org/v1")
+            .and(predicate::str::contains(&ephemeral_identity))
+            .and(predicate::str::contains("chunks:              2"))
+            .and(predicate::str::contains("plaintext length:    70000"))
+            .and(predicate::str::contains("structurally valid:  yes")));
+
+    // Leave 16 octets of the final chunk, fewer than its tag
+    let truncated = &ciphertext[..55 + 65536 + 32 + 16];
+    let output = zorn().arg("inspect").arg("--json")
+        .write_stdin(truncated)
+        .assert()
+        .success()
+        .get_output().stdout.clone();
+    let json: serde_json::Value = serde_json::from_slice(&output).expect("Output should be JSON, but");
+    assert_eq!(json, serde_json::json!({
+        "version_line": "zorn-encryption.org/v1",
+        "ephemeral_identity": ephemeral_identity,
+        "ciphertext_len": truncated.len(),
+        "payload_len": truncated.len() - 55,
+        "chunks": 2,
+        "plaintext_len": null,
+        "valid": false,
+    }));
+
+    zorn().arg("inspect")
+        .write_stdin(&b"zorn-encryption.org/v2\n"[..])
+        .assert()
+        .code(3);
+}
+
+#[test]
+fn verify_checks_whole_message() {
+    let dir = TempDir::new().expect("Creating a temporary directory should succeed, but");
+    let alice = party(dir.path(), "alice", [1u8; 32]);
+    let bob = party(dir.path(), "bob", [2u8; 32]);
+    let message = dir.path().join("message.zorn");
+    let ciphertext = encrypt(&alice, &bob, &vec![0u8; 200000]);
+    std::fs::write(&message, &ciphertext).expect("Writing a test file should succeed, but");
+    let verify = || {
+        let mut cmd = zorn();
+        cmd.arg("verify").arg("--from").arg(&alice.identity).arg("--identity").arg(&bob.secret_file);
+        cmd
+    };
+
+    verify().arg(&message)
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty());
+
+    let mut flipped = ciphertext.clone();
+    flipped[55 + 2 * 65568 + 10] ^= 1;
+    verify().write_stdin(flipped)
+        .assert()
+        .code(5)
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains("verification failed at chunk 2"));
+
+    verify().write_stdin(&ciphertext[..55 + 65568])
+        .assert()
+        .code(6)
+        .stderr(predicate::str::contains("verification failed at chunk 1"));
+}
+
+#[test]
+fn keygen_writes_usable_identity_file() {
+    let dir = TempDir::new().expect("Creating a temporary directory should succeed, but");
+    let secret_file = dir.path().join("key");
+
+    let identity = zorn().arg("keygen")
+        .arg("-o").arg(&secret_file)
+        .assert()
+        .success()
+        .get_output().stdout.clone();
+    let identity = String::from_utf8(identity).expect("Identity should be UTF-8, but").trim_end().to_owned();
+    let bob = Party { secret_file, identity };
+
+    #[cfg(unix)] {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(std::fs::metadata(&bob.secret_file).expect("Reading metadata should succeed, but").permissions().mode() & 0o777, 0o600);
+    }
+    let contents = std::fs::read_to_string(&bob.secret_file).expect("Reading a test file should succeed, but");
+    assert!(contents.contains(&format!("# public identity: {}", bob.identity)));
+
+    let alice = party(dir.path(), "alice", [1u8; 32]);
+    zorn().arg("decrypt")
+        .arg("--from").arg(&alice.identity)
+        .arg("--identity").arg(&bob.secret_file)
+        .write_stdin(encrypt(&alice, &bob, b"hello"))
+        .assert()
+        .success()
+        .stdout("hello");
+}
+
+#[test]
+fn keygen_refuses_to_overwrite() {
+    let dir = TempDir::new().expect("Creating a temporary directory should succeed, but");
+    let secret_file = dir.path().join("key");
+    std::fs::write(&secret_file, "precious").expect("Writing a test file should succeed, but");
+
+    zorn().arg("keygen")
+        .arg("-o").arg(&secret_file)
+        .assert()
+        .failure();
+    assert_eq!(std::fs::read_to_string(&secret_file).expect("Reading a test file should succeed, but"), "precious");
+
+    zorn().arg("keygen")
+        .arg("-o").arg(&secret_file)
+        .arg("--force")
+        .assert()
+        .success();
+    assert_ne!(std::fs::read_to_string(&secret_file).expect("Reading a test file should succeed, but"), "precious");
+}
+
+#[test]
+fn keygen_derives_identities_from_master_secret() {
+    let dir = TempDir::new().expect("Creating a temporary directory should succeed, but");
+    let master_file = dir.path().join("master");
+
+    zorn().arg("keygen").arg("--master")
+        .arg("-o").arg(&master_file)
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty());
+    assert!(std::fs::read_to_string(&master_file).expect("Reading a test file should succeed, but").contains("ZORN-MASTER-SECRET-1"));
+
+    let derive = |label: Option<&str>, output: &str| {
+        let mut cmd = zorn();
+        cmd.arg("keygen").arg("--from-master").arg(&master_file).arg("-o").arg(dir.path().join(output));
+        if let Some(label) = label {
+            cmd.arg("--label").arg(label);
+        }
+        String::from_utf8(cmd.assert().success().get_output().stdout.clone()).expect("Output should be UTF-8, but")
+    };
+
+    let backup = derive(Some("backup"), "backup-1");
+    assert_eq!(backup, derive(Some("backup"), "backup-2"));
+    assert_ne!(backup, derive(Some("mail"), "mail"));
+    assert_ne!(backup, derive(None, "default"));
+}
@@ -0,0 +1,516 @@
+use std::io;
+use std::ops::Range;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use bytes::{Buf, Bytes, BytesMut};
+use rand_core::{CryptoRng, OsRng, RngCore};
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+use tokio_util::codec::{Decoder, Encoder};
+use zeroize::Zeroizing;
+
+use crate::chunk::PayloadCipher;
+use crate::error::Error;
+use crate::header::{Header, HeaderError};
+use crate::identity::{ZornIdentity, ZornIdentitySecret};
+use crate::padding::PaddingRule;
+use crate::stream::{ChunkOpener, State, CHUNK_SIZE, ENCRYPTED_CHUNK_SIZE, TAG_SIZE};
+
+// Header::read_from never reads past the header, so feeding it one more
+// octet at a time finds the end of the header without any lookahead
+async fn read_header<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Header, Error> {
+    let mut buf = Vec::new();
+    loop {
+        match Header::read_from(&buf[..]) {
+            Err(HeaderError::Truncated) => {}
+            result => return Ok(result?),
+        }
+        match reader.read_u8().await {
+            Ok(b) => buf.push(b),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Err(HeaderError::Truncated.into()),
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+// The payload is only finished by shutdown(); dropping an AsyncEncryptor
+// before that produces a truncated payload
+pub struct AsyncEncryptor<W: AsyncWrite + Unpin> {
+    writer: W,
+    cipher: PayloadCipher,
+    // Holds plaintext until a chunk is sealed, then the encrypted chunk until
+    // it has been written out
+    buffer: Zeroizing<Vec<u8>>,
+    len: usize,
+    pending: Range<usize>,
+    finished: bool,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncEncryptor<W> {
+    // The header is written along with the first chunk
+    pub fn new(writer: W, sender_secret: &ZornIdentitySecret, recipient_identity: &ZornIdentity) -> Result<Self, Error> {
+        Self::with_rng(writer, OsRng, sender_secret, recipient_identity)
+    }
+
+    pub fn with_rng<T: RngCore + CryptoRng>(writer: W, csprng: T, sender_secret: &ZornIdentitySecret, recipient_identity: &ZornIdentity) -> Result<Self, Error> {
+        let (header, cipher) = PayloadCipher::for_sender(csprng, sender_secret, recipient_identity)?;
+        let header = header.to_bytes();
+        let mut buffer = Zeroizing::new(vec![0u8; ENCRYPTED_CHUNK_SIZE]);
+        buffer[..header.len()].copy_from_slice(&header);
+
+        Ok(AsyncEncryptor { writer, cipher, buffer, len: 0, pending: 0..header.len(), finished: false })
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn seal_chunk(&mut self, last: bool) -> io::Result<()> {
+        let len = std::mem::take(&mut self.len);
+        let (plaintext, tag) = self.buffer.split_at_mut(len);
+        tag[..TAG_SIZE].copy_from_slice(&self.cipher.seal(plaintext, last, false)?);
+        self.pending = 0..len + TAG_SIZE;
+        Ok(())
+    }
+
+    fn poll_write_pending(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.pending.is_empty() {
+            let n = ready!(Pin::new(&mut self.writer).poll_write(cx, &self.buffer[self.pending.clone()]))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.pending.start += n;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for AsyncEncryptor<W> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.finished {
+            return Poll::Ready(Err(io::Error::other("payload has already been finished")));
+        }
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        ready!(this.poll_write_pending(cx))?;
+        // A full chunk is only sealed once more plaintext arrives, since the
+        // last chunk may itself be full and needs the last-chunk flag
+        if this.len == CHUNK_SIZE {
+            this.seal_chunk(false)?;
+            ready!(this.poll_write_pending(cx))?;
+        }
+
+        let n = buf.len().min(CHUNK_SIZE - this.len);
+        this.buffer[this.len..this.len + n].copy_from_slice(&buf[..n]);
+        this.len += n;
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_pending(cx))?;
+        Pin::new(&mut this.writer).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.finished {
+            ready!(this.poll_write_pending(cx))?;
+            this.seal_chunk(true)?;
+            this.finished = true;
+        }
+        ready!(this.poll_write_pending(cx))?;
+        Pin::new(&mut this.writer).poll_shutdown(cx)
+    }
+}
+
+// The asynchronous counterpart of Decryptor with the same guarantees: errors
+// carry a StreamError as their inner error and plaintext is only released
+// after the chunk containing it has been authenticated
+pub struct AsyncDecryptor<R: AsyncRead + Unpin> {
+    reader: R,
+    opener: ChunkOpener,
+    // One encrypted chunk followed by a single octet of lookahead
+    buffer: Zeroizing<Vec<u8>>,
+    filled: usize,
+    plaintext: Range<usize>,
+    state: State,
+}
+
+impl<R: AsyncRead + Unpin> AsyncDecryptor<R> {
+    pub async fn new(reader: R, recipient_secret: &ZornIdentitySecret, sender_identity: &ZornIdentity) -> Result<Self, Error> {
+        Self::with_identities(reader, std::slice::from_ref(recipient_secret), sender_identity).await
+    }
+
+    pub async fn with_identities(mut reader: R, recipient_secrets: &[ZornIdentitySecret], sender_identity: &ZornIdentity) -> Result<Self, Error> {
+        let header = read_header(&mut reader).await?;
+        Ok(AsyncDecryptor {
+            reader,
+            opener: ChunkOpener::new(&header, header.encoded_len() as u64, PaddingRule::from(header.version), recipient_secrets, sender_identity)?,
+            buffer: Zeroizing::new(vec![0u8; ENCRYPTED_CHUNK_SIZE + 1]),
+            filled: 0,
+            plaintext: 0..0,
+            state: State::Reading,
+        })
+    }
+
+    pub fn authenticated_bytes(&self) -> u64 {
+        self.opener.authenticated_bytes()
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn poll_open_chunk(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.filled == self.buffer.len() {
+            self.buffer[0] = self.buffer[ENCRYPTED_CHUNK_SIZE];
+            self.filled = 1;
+        }
+
+        while self.filled < self.buffer.len() {
+            let mut buf = ReadBuf::new(&mut self.buffer[self.filled..]);
+            ready!(Pin::new(&mut self.reader).poll_read(cx, &mut buf))?;
+            match buf.filled().len() {
+                0 => break,
+                n => self.filled += n,
+            }
+        }
+
+        let last = self.filled < self.buffer.len();
+        let len = self.filled.min(ENCRYPTED_CHUNK_SIZE);
+        let (plaintext_len, state) = self.opener.open(&mut self.buffer[..len], last)?;
+        self.plaintext = 0..plaintext_len;
+        self.state = state;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncBufRead for AsyncDecryptor<R> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        while this.plaintext.is_empty() {
+            match this.state {
+                State::Reading => ready!(this.poll_open_chunk(cx))?,
+                State::Finished => break,
+                State::Failed(e) => return Poll::Ready(Err(e.into())),
+            }
+        }
+        Poll::Ready(Ok(&this.buffer[this.plaintext.clone()]))
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        let this = self.get_mut();
+        this.plaintext.start = (this.plaintext.start + amt).min(this.plaintext.end);
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for AsyncDecryptor<R> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let available = ready!(self.as_mut().poll_fill_buf(cx))?;
+        let n = buf.remaining().min(available.len());
+        buf.put_slice(&available[..n]);
+        self.consume(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+// Yields the plaintext of every chunk as soon as it has been authenticated.
+// Unlike the decryptors, the plaintext handed out as Bytes is not zeroized.
+pub struct ZornDecoder {
+    recipient_secrets: Vec<ZornIdentitySecret>,
+    sender_identity: ZornIdentity,
+    opener: Option<ChunkOpener>,
+    state: State,
+}
+
+impl ZornDecoder {
+    pub fn new(recipient_secrets: Vec<ZornIdentitySecret>, sender_identity: ZornIdentity) -> Self {
+        ZornDecoder { recipient_secrets, sender_identity, opener: None, state: State::Reading }
+    }
+
+    pub fn authenticated_bytes(&self) -> u64 {
+        self.opener.as_ref().map_or(0, ChunkOpener::authenticated_bytes)
+    }
+
+    fn open_chunk(&mut self, src: &mut BytesMut, len: usize, last: bool) -> Result<Option<Bytes>, Error> {
+        let opener = self.opener.as_mut().expect("The header has been read");
+        let mut chunk = src.split_to(len);
+        let (plaintext_len, state) = opener.open(&mut chunk, last)?;
+        self.state = state;
+
+        match state {
+            State::Failed(e) if plaintext_len == 0 => Err(e.into()),
+            _ if plaintext_len == 0 => Ok(None),
+            _ => {
+                chunk.truncate(plaintext_len);
+                Ok(Some(chunk.freeze()))
+            }
+        }
+    }
+}
+
+impl Decoder for ZornDecoder {
+    type Item = Bytes;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Bytes>, Error> {
+        match self.state {
+            State::Reading => {}
+            State::Finished => return Ok(None),
+            State::Failed(e) => return Err(e.into()),
+        }
+
+        if self.opener.is_none() {
+            let header = match Header::read_from(&src[..]) {
+                Err(HeaderError::Truncated) => return Ok(None),
+                header => header?,
+            };
+            src.advance(header.encoded_len());
+            self.opener = Some(ChunkOpener::new(&header, header.encoded_len() as u64, PaddingRule::from(header.version), &self.recipient_secrets, &self.sender_identity)?);
+            self.recipient_secrets.clear();
+        }
+
+        // Only more data after a full chunk shows that it isn't the last one.
+        // Padding yields nothing, but more chunks may already be buffered.
+        while self.state == State::Reading {
+            if src.len() <= ENCRYPTED_CHUNK_SIZE {
+                src.reserve(ENCRYPTED_CHUNK_SIZE + 1 - src.len());
+                return Ok(None);
+            }
+            if let Some(plaintext) = self.open_chunk(src, ENCRYPTED_CHUNK_SIZE, false)? {
+                return Ok(Some(plaintext));
+            }
+        }
+        Ok(None)
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Bytes>, Error> {
+        if let Some(plaintext) = self.decode(src)? {
+            return Ok(Some(plaintext));
+        }
+        if self.opener.is_none() {
+            return Err(HeaderError::Truncated.into());
+        }
+
+        match self.state {
+            State::Reading => self.open_chunk(src, src.len(), true),
+            State::Finished => Ok(None),
+            State::Failed(e) => Err(e.into()),
+        }
+    }
+}
+
+// Marks the end of the plaintext for ZornEncoder, which then emits the
+// final chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EndOfMessage;
+
+// Encodes plaintext into a complete message, including the header. Sending
+// EndOfMessage is required to produce a message that isn't truncated.
+pub struct ZornEncoder {
+    header: Option<Header>,
+    cipher: PayloadCipher,
+    buffer: Zeroizing<Vec<u8>>,
+    finished: bool,
+}
+
+impl ZornEncoder {
+    pub fn new(sender_secret: &ZornIdentitySecret, recipient_identity: &ZornIdentity) -> Result<Self, Error> {
+        Self::with_rng(OsRng, sender_secret, recipient_identity)
+    }
+
+    pub fn with_rng<T: RngCore + CryptoRng>(csprng: T, sender_secret: &ZornIdentitySecret, recipient_identity: &ZornIdentity) -> Result<Self, Error> {
+        let (header, cipher) = PayloadCipher::for_sender(csprng, sender_secret, recipient_identity)?;
+        Ok(ZornEncoder {
+            header: Some(header),
+            cipher,
+            buffer: Zeroizing::new(Vec::with_capacity(CHUNK_SIZE)),
+            finished: false,
+        })
+    }
+
+    fn start(&mut self, dst: &mut BytesMut) -> Result<(), Error> {
+        if self.finished {
+            return Err(io::Error::other("payload has already been finished").into());
+        }
+        if let Some(header) = self.header.take() {
+            dst.extend_from_slice(&header.to_bytes());
+        }
+        Ok(())
+    }
+
+    fn seal_chunk(&mut self, dst: &mut BytesMut, last: bool) -> Result<(), Error> {
+        let tag = self.cipher.seal(&mut self.buffer, last, false)?;
+        dst.reserve(self.buffer.len() + TAG_SIZE);
+        dst.extend_from_slice(&self.buffer);
+        dst.extend_from_slice(&tag);
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+impl Encoder<Bytes> for ZornEncoder {
+    type Error = Error;
+
+    fn encode(&mut self, item: Bytes, dst: &mut BytesMut) -> Result<(), Error> {
+        self.start(dst)?;
+
+        let mut plaintext = &item[..];
+        while !plaintext.is_empty() {
+            if self.buffer.len() == CHUNK_SIZE {
+                self.seal_chunk(dst, false)?;
+            }
+            let n = plaintext.len().min(CHUNK_SIZE - self.buffer.len());
+            self.buffer.extend_from_slice(&plaintext[..n]);
+            plaintext = &plaintext[n..];
+        }
+        Ok(())
+    }
+}
+
+impl Encoder<EndOfMessage> for ZornEncoder {
+    type Error = Error;
+
+    fn encode(&mut self, _: EndOfMessage, dst: &mut BytesMut) -> Result<(), Error> {
+        self.start(dst)?;
+        self.seal_chunk(dst, true)?;
+        self.finished = true;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::assert_matches::assert_matches;
+    use std::io::Write;
+    use bytes::Bytes;
+    use futures_util::{SinkExt, StreamExt};
+    use rand_core::OsRng;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_util::codec::{FramedRead, FramedWrite};
+
+    use crate::error::Error;
+    use crate::identity::{ZornIdentity, ZornIdentitySecret};
+    use crate::padding::Padding;
+    use crate::stream::{Encryptor, StreamError, CHUNK_SIZE, ENCRYPTED_CHUNK_SIZE};
+    use crate::test_util::{decrypt, encrypt, stream_error};
+
+    use super::{AsyncDecryptor, AsyncEncryptor, EndOfMessage, ZornDecoder, ZornEncoder};
+
+    async fn async_decrypt(sender_secret: &ZornIdentitySecret, recipient_secret: &ZornIdentitySecret, file: &[u8]) -> (Vec<u8>, Result<(), StreamError>) {
+        let mut d = AsyncDecryptor::new(file, recipient_secret, &ZornIdentity::from(sender_secret)).await.expect("Header should be valid, but");
+        let mut plaintext = Vec::new();
+        let result = d.read_to_end(&mut plaintext).await.map(|_| ()).map_err(stream_error);
+        assert_eq!(d.authenticated_bytes(), plaintext.len() as u64);
+        (plaintext, result)
+    }
+
+    async fn decode(sender_secret: &ZornIdentitySecret, recipient_secret: &ZornIdentitySecret, file: &[u8]) -> (Vec<u8>, Result<(), Error>) {
+        let decoder = ZornDecoder::new(vec![recipient_secret.to_string().parse().expect("Secret key encoding should roundtrip, but")], ZornIdentity::from(sender_secret));
+        let mut frames = FramedRead::new(file, decoder);
+        let mut plaintext = Vec::new();
+        while let Some(chunk) = frames.next().await {
+            match chunk {
+                Ok(chunk) => plaintext.extend_from_slice(&chunk),
+                Err(e) => return (plaintext, Err(e)),
+            }
+        }
+        (plaintext, Ok(()))
+    }
+
+    fn modified_files(file: &[u8]) -> Vec<Vec<u8>> {
+        let mut files: Vec<_> = [55, 55 + ENCRYPTED_CHUNK_SIZE, 55 + 2 * ENCRYPTED_CHUNK_SIZE + 31, 55 + 2 * ENCRYPTED_CHUNK_SIZE + 100]
+            .into_iter().map(|len| file[..len].to_vec()).collect();
+        let mut modified = file.to_vec();
+        modified[55 + ENCRYPTED_CHUNK_SIZE + 7] ^= 1;
+        files.push(modified);
+        files.push(file.to_vec());
+        files
+    }
+
+    #[tokio::test]
+    async fn async_encryptor_roundtrip() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+
+        for len in [0, 1, CHUNK_SIZE, CHUNK_SIZE + 1, 3 * CHUNK_SIZE] {
+            let plaintext: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let mut e = AsyncEncryptor::new(Vec::new(), &sender_secret, &ZornIdentity::from(&recipient_secret)).expect("Key exchange should succeed, but");
+            for part in plaintext.chunks(1000) {
+                e.write_all(part).await.expect("Writing to a Vec should succeed, but");
+            }
+            e.shutdown().await.expect("Writing to a Vec should succeed, but");
+            let file = e.into_inner();
+
+            assert_eq!(file.len(), encrypt(&sender_secret, &recipient_secret, &plaintext).len());
+            assert_eq!(decrypt(&sender_secret, &recipient_secret, &file), (plaintext, Ok(())));
+        }
+    }
+
+    #[tokio::test]
+    async fn async_decryptor_agrees_with_decryptor() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+        let plaintext: Vec<u8> = (0..3 * CHUNK_SIZE).map(|i| i as u8).collect();
+
+        for file in modified_files(&encrypt(&sender_secret, &recipient_secret, &plaintext)) {
+            assert_eq!(async_decrypt(&sender_secret, &recipient_secret, &file).await, decrypt(&sender_secret, &recipient_secret, &file));
+        }
+    }
+
+    #[tokio::test]
+    async fn decoder_agrees_with_decryptor() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+        let plaintext: Vec<u8> = (0..3 * CHUNK_SIZE).map(|i| i as u8).collect();
+
+        for file in modified_files(&encrypt(&sender_secret, &recipient_secret, &plaintext)) {
+            let (expected, expected_result) = decrypt(&sender_secret, &recipient_secret, &file);
+            let (decoded, result) = decode(&sender_secret, &recipient_secret, &file).await;
+            assert_eq!(decoded, expected);
+            match expected_result {
+                Ok(()) => assert_matches!(result, Ok(())),
+                Err(e) => assert_eq!(result.map_err(|e| e.to_string()), Err(Error::from(e).to_string())),
+            }
+        }
+
+        assert_matches!(decode(&sender_secret, &recipient_secret, b"zorn-encryption.org/v1\n").await, (_, Err(Error::Header(_))));
+    }
+
+    // Chunks of nothing but padding yield no plaintext at all
+    #[tokio::test]
+    async fn decoders_skip_padding() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+        let plaintext = vec![0x5au8; CHUNK_SIZE + 10];
+        let mut e = Encryptor::new(Vec::new(), &sender_secret, &ZornIdentity::from(&recipient_secret)).expect("Writing to a Vec should succeed, but")
+            .with_padding(Padding::To(5 * CHUNK_SIZE as u64));
+        e.write_all(&plaintext).expect("Writing to a Vec should succeed, but");
+        let file = e.finish().expect("Writing to a Vec should succeed, but");
+
+        assert_eq!(async_decrypt(&sender_secret, &recipient_secret, &file).await, (plaintext.clone(), Ok(())));
+        assert_matches!(decode(&sender_secret, &recipient_secret, &file).await, (decoded, Ok(())) if decoded == plaintext);
+    }
+
+    #[tokio::test]
+    async fn encoder_roundtrip() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+        let plaintext: Vec<u8> = (0..2 * CHUNK_SIZE + 5).map(|i| i as u8).collect();
+
+        let encoder = ZornEncoder::new(&sender_secret, &ZornIdentity::from(&recipient_secret)).expect("Key exchange should succeed, but");
+        let mut frames = FramedWrite::new(Vec::new(), encoder);
+        for part in plaintext.chunks(CHUNK_SIZE / 3) {
+            frames.send(Bytes::copy_from_slice(part)).await.expect("Writing to a Vec should succeed, but");
+        }
+        frames.send(EndOfMessage).await.expect("Writing to a Vec should succeed, but");
+        assert_matches!(frames.send(Bytes::from_static(b"more")).await, Err(Error::Io(_)));
+
+        let file = frames.into_inner();
+        assert_eq!(decrypt(&sender_secret, &recipient_secret, &file), (plaintext.clone(), Ok(())));
+        assert_eq!(decode(&sender_secret, &recipient_secret, &file).await.0, plaintext);
+    }
+}
@@ -0,0 +1,258 @@
+use aead::{AeadInPlace, KeyInit, Nonce, Tag, generic_array::GenericArray};
+use rand_core::{CryptoRng, RngCore};
+use thiserror::Error;
+use x25519_dalek::PublicKey;
+
+use crate::header::{Header, HEADER_LEN};
+use crate::identity::{ZornIdentity, ZornIdentitySecret};
+use crate::key_exchange::{KeyExchangeError, SharedSecret};
+use crate::xchacha20_blake3::XChaCha20Blake3;
+
+pub const CHUNK_SIZE: usize = 1 << 16;
+pub const TAG_SIZE: usize = 32;
+pub const ENCRYPTED_CHUNK_SIZE: usize = CHUNK_SIZE + TAG_SIZE;
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkError {
+    #[error("invalid plaintext length {0} for this chunk")]
+    InvalidLength(usize),
+    #[error("buffer has no room for the tag")]
+    BufferTooSmall,
+    #[error("payload exceeds the maximum number of chunks")]
+    TooManyChunks,
+    #[error("payload has already been finished")]
+    Finished,
+    #[error("message is too long for the padding")]
+    TooLongForPadding,
+}
+
+#[cfg(feature = "std")]
+impl From<ChunkError> for std::io::Error {
+    fn from(e: ChunkError) -> Self {
+        std::io::Error::other(e)
+    }
+}
+
+// Flags in the first word of a chunk nonce
+const LAST_CHUNK: u64 = 1;
+const PADDED_CHUNK: u64 = 2;
+
+pub(crate) fn chunk_nonce(n: u64, last: bool, padded: bool) -> Nonce<XChaCha20Blake3> {
+    let flags = if last { LAST_CHUNK } else { 0 } | if padded { PADDED_CHUNK } else { 0 };
+    let mut nonce = Nonce::<XChaCha20Blake3>::default();
+    nonce[..8].copy_from_slice(&flags.to_le_bytes());
+    nonce[16..].copy_from_slice(&n.to_le_bytes());
+    nonce
+}
+
+pub(crate) struct PayloadCipher {
+    aead: XChaCha20Blake3,
+    ad: [u8; 96],
+    index: u64,
+}
+
+impl PayloadCipher {
+    pub(crate) fn new(shared_secret: &SharedSecret, ephemeral_identity: &PublicKey, sender_identity: &ZornIdentity, recipient_identity: &ZornIdentity) -> Self {
+        let mut ad = [0u8; 96];
+        ad[..32].copy_from_slice(ephemeral_identity.as_bytes());
+        ad[32..64].copy_from_slice(sender_identity.as_bytes());
+        ad[64..].copy_from_slice(recipient_identity.as_bytes());
+
+        PayloadCipher {
+            aead: XChaCha20Blake3::new(GenericArray::from_slice(shared_secret.as_bytes())),
+            ad,
+            index: 0,
+        }
+    }
+
+    // Returns the first of count consecutive chunk indices
+    pub(crate) fn reserve_indices(&mut self, count: u64) -> Result<u64, ChunkError> {
+        let n = self.index;
+        self.index = n.checked_add(count).ok_or(ChunkError::TooManyChunks)?;
+        Ok(n)
+    }
+
+    pub(crate) fn seal(&mut self, buffer: &mut [u8], last: bool, padded: bool) -> Result<Tag<XChaCha20Blake3>, ChunkError> {
+        let n = self.reserve_indices(1)?;
+        self.seal_at(n, buffer, last, padded)
+    }
+
+    pub(crate) fn seal_at(&self, n: u64, buffer: &mut [u8], last: bool, padded: bool) -> Result<Tag<XChaCha20Blake3>, ChunkError> {
+        self.aead.encrypt_in_place_detached(&chunk_nonce(n, last, padded), &self.ad, buffer)
+            .map_err(|_| ChunkError::InvalidLength(buffer.len()))
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn open(&self, n: u64, buffer: &mut [u8], tag: &[u8], last: bool, padded: bool) -> aead::Result<()> {
+        self.aead.decrypt_in_place_detached(&chunk_nonce(n, last, padded), &self.ad, buffer, Tag::<XChaCha20Blake3>::from_slice(tag))
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn verify(&self, n: u64, ciphertext: &[u8], tag: &[u8], last: bool, padded: bool) -> aead::Result<()> {
+        self.aead.verify_detached(&chunk_nonce(n, last, padded), &self.ad, ciphertext, Tag::<XChaCha20Blake3>::from_slice(tag))
+    }
+
+    // Tries the expected flags first and reports the ones that authenticated
+    #[cfg(feature = "std")]
+    pub(crate) fn open_any(&self, n: u64, buffer: &mut [u8], tag: &[u8], last: bool, padded: bool, may_pad: bool) -> Option<ChunkFlags> {
+        any_flags(last, padded, may_pad, |flags| self.open(n, buffer, tag, flags.last, flags.padded))
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn verify_any(&self, n: u64, ciphertext: &[u8], tag: &[u8], last: bool, padded: bool, may_pad: bool) -> Option<ChunkFlags> {
+        any_flags(last, padded, may_pad, |flags| self.verify(n, ciphertext, tag, flags.last, flags.padded))
+    }
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ChunkFlags {
+    pub(crate) last: bool,
+    pub(crate) padded: bool,
+}
+
+// Only padding may follow padding, so once it has started, or where the
+// version rules padding out, only the last-chunk flag is in question
+#[cfg(feature = "std")]
+fn any_flags(last: bool, padded: bool, may_pad: bool, mut check: impl FnMut(ChunkFlags) -> aead::Result<()>) -> Option<ChunkFlags> {
+    let candidates = [(last, padded), (!last, padded), (last, true), (!last, true)];
+    candidates[..if padded || !may_pad { 2 } else { 4 }].iter()
+        .map(|&(last, padded)| ChunkFlags { last, padded })
+        .find(|&flags| check(flags).is_ok())
+}
+
+// Encrypts a payload one chunk at a time in buffers owned by the caller, for
+// senders without std or an allocator. A zorn file is header() followed by
+// every sealed chunk in order, where only the last one may be short.
+pub struct ChunkEncryptor {
+    cipher: PayloadCipher,
+    header: [u8; HEADER_LEN],
+    finished: bool,
+}
+
+impl ChunkEncryptor {
+    pub fn new<T: RngCore + CryptoRng>(csprng: T, sender_secret: &ZornIdentitySecret, recipient_identity: &ZornIdentity) -> Result<Self, KeyExchangeError> {
+        let (ephemeral_identity, shared_secret) = SharedSecret::sender_with_rng(csprng, sender_secret, recipient_identity)?;
+        Ok(ChunkEncryptor {
+            cipher: PayloadCipher::new(&shared_secret, &ephemeral_identity, &ZornIdentity::from(sender_secret), recipient_identity),
+            header: Header::new(ephemeral_identity).to_bytes(),
+            finished: false,
+        })
+    }
+
+    pub fn header(&self) -> &[u8; HEADER_LEN] {
+        &self.header
+    }
+
+    // Encrypts the first len octets of buffer in place and writes the tag
+    // right after them, returning the length of the encrypted chunk
+    pub fn seal_chunk(&mut self, buffer: &mut [u8], len: usize, last: bool) -> Result<usize, ChunkError> {
+        if self.finished {
+            return Err(ChunkError::Finished);
+        }
+        if len > CHUNK_SIZE || (len < CHUNK_SIZE && !last) {
+            return Err(ChunkError::InvalidLength(len));
+        }
+        let Some(chunk) = buffer.get_mut(..len + TAG_SIZE) else {
+            return Err(ChunkError::BufferTooSmall);
+        };
+
+        let (plaintext, tag) = chunk.split_at_mut(len);
+        tag.copy_from_slice(&self.cipher.seal(plaintext, last, false)?);
+        self.finished = last;
+        Ok(len + TAG_SIZE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "std")]
+    use std::io::Read;
+    use aead::{AeadInPlace, Tag};
+    #[cfg(feature = "std")]
+    use rand_core::OsRng;
+    use rand_chacha::ChaCha20Rng;
+    use rand_core::SeedableRng;
+    use x25519_dalek::PublicKey;
+
+    use crate::header::HEADER_LEN;
+    use crate::identity::{ZornIdentity, ZornIdentitySecret};
+    use crate::key_exchange::SharedSecret;
+    use crate::xchacha20_blake3::XChaCha20Blake3;
+    #[cfg(feature = "std")]
+    use crate::stream::Decryptor;
+
+    use super::{ChunkEncryptor, ChunkError, PayloadCipher, chunk_nonce, CHUNK_SIZE, ENCRYPTED_CHUNK_SIZE, TAG_SIZE};
+
+    #[test]
+    fn chunk_nonce_layout() {
+        assert_eq!(chunk_nonce(0x0102, false, false).as_slice(), &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(chunk_nonce(0x0102, true, false).as_slice(), &[1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(chunk_nonce(0x0102, false, true).as_slice(), &[2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(chunk_nonce(0x0102, true, true).as_slice(), &[3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn chunk_encryptor_roundtrip() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+        let plaintext: Vec<u8> = (0..2 * CHUNK_SIZE + 100).map(|i| i as u8).collect();
+
+        let mut e = ChunkEncryptor::new(OsRng, &sender_secret, &ZornIdentity::from(&recipient_secret)).expect("Key exchange should succeed, but");
+        let mut file = e.header().to_vec();
+        let mut buffer = [0u8; ENCRYPTED_CHUNK_SIZE];
+        let chunks: Vec<_> = plaintext.chunks(CHUNK_SIZE).collect();
+        for (i, chunk) in chunks.iter().enumerate() {
+            buffer[..chunk.len()].copy_from_slice(chunk);
+            let len = e.seal_chunk(&mut buffer, chunk.len(), i == chunks.len() - 1).expect("Chunk should be valid, but");
+            file.extend_from_slice(&buffer[..len]);
+        }
+
+        let mut d = Decryptor::new(&file[..], &recipient_secret, &ZornIdentity::from(&sender_secret)).expect("Header should be valid, but");
+        let mut decrypted = Vec::new();
+        d.read_to_end(&mut decrypted).expect("File should be valid, but");
+        assert_eq!(decrypted, plaintext);
+    }
+
+    // Everything a sender without std has, opened by hand in a single buffer
+    #[test]
+    fn chunk_encryptor_roundtrip_in_caller_buffers() {
+        let mut rng = ChaCha20Rng::from_seed([7u8; 32]);
+        let sender_secret = ZornIdentitySecret::try_new(&mut rng).expect("ChaCha20Rng should not fail, but");
+        let recipient_secret = ZornIdentitySecret::try_new(&mut rng).expect("ChaCha20Rng should not fail, but");
+        let (sender_identity, recipient_identity) = (ZornIdentity::from(&sender_secret), ZornIdentity::from(&recipient_secret));
+
+        let mut e = ChunkEncryptor::new(&mut rng, &sender_secret, &recipient_identity).expect("Key exchange should succeed, but");
+        let ephemeral_identity = PublicKey::from(<[u8; 32]>::try_from(&e.header()[HEADER_LEN - 32..]).expect("The header ends in the ephemeral identity"));
+        let shared_secret = SharedSecret::recipient(&recipient_secret, &sender_identity, &ephemeral_identity).expect("Key exchange should succeed, but");
+        let cipher = PayloadCipher::new(&shared_secret, &ephemeral_identity, &sender_identity, &recipient_identity);
+
+        let mut buffer = [0u8; ENCRYPTED_CHUNK_SIZE];
+        for (n, (len, last)) in [(CHUNK_SIZE, false), (100, true)].into_iter().enumerate() {
+            buffer[..len].fill(n as u8 + 1);
+            assert_eq!(e.seal_chunk(&mut buffer, len, last), Ok(len + TAG_SIZE));
+            assert!(buffer[..len].iter().any(|&b| b != n as u8 + 1));
+
+            let (ciphertext, tag) = buffer[..len + TAG_SIZE].split_at_mut(len);
+            cipher.aead.decrypt_in_place_detached(&chunk_nonce(n as u64, last, false), &cipher.ad, ciphertext, Tag::<XChaCha20Blake3>::from_slice(tag))
+                .expect("The sealed chunk should authenticate, but");
+            assert!(ciphertext.iter().all(|&b| b == n as u8 + 1));
+        }
+    }
+
+    #[test]
+    fn chunk_encryptor_rejects_invalid_chunks() {
+        let mut rng = ChaCha20Rng::from_seed([7u8; 32]);
+        let sender_secret = ZornIdentitySecret::new(&mut rng);
+        let recipient_identity = ZornIdentity::from(&ZornIdentitySecret::new(&mut rng));
+        let mut e = ChunkEncryptor::new(&mut rng, &sender_secret, &recipient_identity).expect("Key exchange should succeed, but");
+        let mut buffer = [0u8; ENCRYPTED_CHUNK_SIZE + 1];
+
+        assert_eq!(e.seal_chunk(&mut buffer, CHUNK_SIZE - 1, false), Err(ChunkError::InvalidLength(CHUNK_SIZE - 1)));
+        assert_eq!(e.seal_chunk(&mut buffer, CHUNK_SIZE + 1, true), Err(ChunkError::InvalidLength(CHUNK_SIZE + 1)));
+        assert_eq!(e.seal_chunk(&mut buffer[..CHUNK_SIZE + 31], CHUNK_SIZE, false), Err(ChunkError::BufferTooSmall));
+        assert_eq!(e.seal_chunk(&mut buffer, 0, true), Ok(32));
+        assert_eq!(e.seal_chunk(&mut buffer, 0, true), Err(ChunkError::Finished));
+    }
+}
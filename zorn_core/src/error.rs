@@ -0,0 +1,120 @@
+use std::io;
+use thiserror::Error;
+
+use crate::chunk::ChunkError;
+use crate::header::HeaderError;
+use crate::identity::{ZornIdentityDecodeError, ZornIdentitySecretDecodeError, ZornMasterSecretDecodeError};
+use crate::identity_file::IdentityFileError;
+use crate::key_exchange::KeyExchangeError;
+use crate::stream::StreamError;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Header(#[from] HeaderError),
+    #[error(transparent)]
+    Identity(#[from] ZornIdentityDecodeError),
+    #[error(transparent)]
+    SecretKey(#[from] ZornIdentitySecretDecodeError),
+    #[error(transparent)]
+    MasterSecret(#[from] ZornMasterSecretDecodeError),
+    #[error(transparent)]
+    IdentityFile(#[from] IdentityFileError),
+    #[error(transparent)]
+    KeyExchange(KeyExchangeError),
+    #[error(transparent)]
+    Chunk(#[from] ChunkError),
+    #[error("authentication failed for chunk {chunk} at offset {offset}")]
+    Authentication { chunk: u64, offset: u64 },
+    #[error("payload is truncated after {authenticated} authenticated bytes")]
+    Truncated { authenticated: u64 },
+    #[error("data follows the final chunk")]
+    TrailingData,
+    #[error("padding in chunk {chunk} is malformed")]
+    Padding { chunk: u64 },
+    #[error("random number generator failed: {0}")]
+    Rng(rand_core::Error),
+    #[error(transparent)]
+    Io(io::Error),
+}
+
+impl From<StreamError> for Error {
+    fn from(e: StreamError) -> Self {
+        match e {
+            StreamError::Authentication { chunk, offset } => Error::Authentication { chunk, offset },
+            StreamError::Truncated { authenticated } => Error::Truncated { authenticated },
+            StreamError::TrailingData => Error::TrailingData,
+            StreamError::Padding { chunk } => Error::Padding { chunk },
+        }
+    }
+}
+
+// RNG failures are reported the same way wherever they happen
+impl From<KeyExchangeError> for Error {
+    fn from(e: KeyExchangeError) -> Self {
+        match e {
+            KeyExchangeError::Rng(e) => Error::Rng(e),
+            e => Error::KeyExchange(e),
+        }
+    }
+}
+
+impl From<rand_core::Error> for Error {
+    fn from(e: rand_core::Error) -> Self {
+        Error::Rng(e)
+    }
+}
+
+// The Read and Write implementations can only return io::Error, so the
+// classification is recovered from the inner error where there is one
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        fn is<E: std::error::Error + 'static>(e: &io::Error) -> bool {
+            e.get_ref().is_some_and(|inner| inner.is::<E>())
+        }
+        fn downcast<E: std::error::Error + 'static>(e: io::Error) -> E {
+            *e.into_inner().expect("Checked by is").downcast().expect("Checked by is")
+        }
+
+        if is::<StreamError>(&e) {
+            downcast::<StreamError>(e).into()
+        } else if is::<HeaderError>(&e) {
+            downcast::<HeaderError>(e).into()
+        } else if is::<KeyExchangeError>(&e) {
+            downcast::<KeyExchangeError>(e).into()
+        } else if is::<ChunkError>(&e) {
+            downcast::<ChunkError>(e).into()
+        } else {
+            Error::Io(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::assert_matches::assert_matches;
+    use std::io;
+    use std::num::NonZeroU32;
+
+    use crate::chunk::ChunkError;
+    use crate::header::HeaderError;
+    use crate::key_exchange::KeyExchangeError;
+    use crate::stream::StreamError;
+
+    use super::Error;
+
+    #[test]
+    fn error_from_io_error_recovers_classification() {
+        assert_matches!(Error::from(io::Error::from(StreamError::Authentication { chunk: 3, offset: 197_079 })),
+            Error::Authentication { chunk: 3, offset: 197_079 });
+        assert_matches!(Error::from(io::Error::from(StreamError::Truncated { authenticated: 42 })), Error::Truncated { authenticated: 42 });
+        assert_matches!(Error::from(io::Error::from(StreamError::TrailingData)), Error::TrailingData);
+        assert_matches!(Error::from(io::Error::from(StreamError::Padding { chunk: 2 })), Error::Padding { chunk: 2 });
+        assert_matches!(Error::from(io::Error::from(HeaderError::MissingPrefix)), Error::Header(HeaderError::MissingPrefix));
+        assert_matches!(Error::from(io::Error::from(KeyExchangeError::NonContributory)), Error::KeyExchange(KeyExchangeError::NonContributory));
+        assert_matches!(Error::from(io::Error::from(KeyExchangeError::Rng(rand_core::Error::from(NonZeroU32::MIN)))), Error::Rng(_));
+        assert_matches!(Error::from(io::Error::from(ChunkError::TooManyChunks)), Error::Chunk(ChunkError::TooManyChunks));
+        assert_matches!(Error::from(io::Error::from(io::ErrorKind::BrokenPipe)), Error::Io(e) if e.kind() == io::ErrorKind::BrokenPipe);
+        assert_matches!(Error::from(io::Error::other("something else")), Error::Io(_));
+    }
+}
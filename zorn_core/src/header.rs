@@ -0,0 +1,231 @@
+#[cfg(feature = "std")]
+use std::io::{self, Read, Write};
+#[cfg(feature = "std")]
+use thiserror::Error;
+
+use x25519_dalek::PublicKey;
+
+pub const VERSION_LINE_PREFIX: &[u8] = b"zorn-encryption.org/";
+#[cfg(feature = "std")]
+const MAX_VERSION_LENGTH: usize = 64;
+
+// Length of an encoded header, which is the same for every version there is
+pub const HEADER_LEN: usize = VERSION_LINE_PREFIX.len() + 3 + 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    V1,
+    // v1 with padded chunks, which older decryptors reject by its version
+    V2,
+}
+
+impl Version {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Version::V1 => "v1",
+            Version::V2 => "v2",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub version: Version,
+    pub ephemeral_identity: PublicKey,
+}
+
+#[cfg(feature = "std")]
+#[derive(Error, Debug)]
+pub enum HeaderError {
+    #[error("version line does not start with zorn-encryption.org/")]
+    MissingPrefix,
+    #[error("version contains the non-VCHAR octet {0:#04x}")]
+    InvalidVersion(u8),
+    #[error("unknown version {0:?}")]
+    UnknownVersion(String),
+    #[error("header is truncated")]
+    Truncated,
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+#[cfg(feature = "std")]
+impl From<HeaderError> for io::Error {
+    fn from(e: HeaderError) -> Self {
+        match e {
+            HeaderError::Io(e) => e,
+            HeaderError::Truncated => io::Error::new(io::ErrorKind::UnexpectedEof, e),
+            _ => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+fn read_exact<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<(), HeaderError> {
+    reader.read_exact(buf).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => HeaderError::Truncated,
+        _ => HeaderError::Io(e),
+    })
+}
+
+#[cfg(feature = "std")]
+fn read_byte<R: Read>(reader: &mut R) -> Result<u8, HeaderError> {
+    let mut b = [0u8; 1];
+    read_exact(reader, &mut b)?;
+    Ok(b[0])
+}
+
+#[cfg(feature = "std")]
+fn read_version<R: Read>(reader: &mut R) -> Result<Version, HeaderError> {
+    for &expected in VERSION_LINE_PREFIX {
+        if read_byte(reader)? != expected {
+            return Err(HeaderError::MissingPrefix);
+        }
+    }
+
+    let mut version = Vec::new();
+    loop {
+        match read_byte(reader)? {
+            b'\n' if !version.is_empty() => break,
+            b @ 0x21..=0x7e if version.len() < MAX_VERSION_LENGTH => version.push(b),
+            b @ 0x21..=0x7e => {
+                version.push(b);
+                return Err(HeaderError::UnknownVersion(String::from_utf8_lossy(&version).into_owned()));
+            }
+            b => return Err(HeaderError::InvalidVersion(b)),
+        }
+    }
+
+    match &version[..] {
+        b"v1" => Ok(Version::V1),
+        b"v2" => Ok(Version::V2),
+        _ => Err(HeaderError::UnknownVersion(String::from_utf8_lossy(&version).into_owned())),
+    }
+}
+
+impl Header {
+    pub fn new(ephemeral_identity: PublicKey) -> Self {
+        Header { version: Version::V1, ephemeral_identity }
+    }
+
+    // Reads octet by octet so that nothing following the header is consumed
+    #[cfg(feature = "std")]
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self, HeaderError> {
+        let version = read_version(&mut reader)?;
+
+        let mut ephemeral_identity = [0u8; 32];
+        read_exact(&mut reader, &mut ephemeral_identity)?;
+
+        Ok(Header { version, ephemeral_identity: PublicKey::from(ephemeral_identity) })
+    }
+
+    pub fn encoded_len(&self) -> usize {
+        VERSION_LINE_PREFIX.len() + self.version.as_str().len() + 1 + 32
+    }
+
+    pub fn to_bytes(&self) -> [u8; HEADER_LEN] {
+        let mut buf = [0u8; HEADER_LEN];
+        let mut offset = 0;
+        for part in [VERSION_LINE_PREFIX, self.version.as_str().as_bytes(), b"\n", self.ephemeral_identity.as_bytes()] {
+            buf[offset..offset + part.len()].copy_from_slice(part);
+            offset += part.len();
+        }
+        buf
+    }
+
+    #[cfg(feature = "std")]
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(&self.to_bytes())
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::assert_matches::assert_matches;
+
+    use x25519_dalek::PublicKey;
+
+    use super::{Header, HeaderError, Version, HEADER_LEN};
+
+    use proptest::{proptest, prelude::any, collection::vec};
+
+    fn v1_header(ephemeral_identity: [u8; 32]) -> Vec<u8> {
+        let mut buf = b"zorn-encryption.org/v1\n".to_vec();
+        buf.extend_from_slice(&ephemeral_identity);
+        buf
+    }
+
+    #[test]
+    fn header_v1_encoding() {
+        let mut buf = Vec::new();
+        let header = Header::new(PublicKey::from([7u8; 32]));
+        header.write_to(&mut buf).expect("Writing to a Vec should succeed, but");
+        assert_eq!(buf, v1_header([7u8; 32]));
+        assert_eq!(buf.len(), header.encoded_len());
+        assert_eq!(buf, header.to_bytes());
+    }
+
+    #[test]
+    fn header_v2_encoding() {
+        let header = Header { version: Version::V2, ephemeral_identity: PublicKey::from([7u8; 32]) };
+        let mut expected = b"zorn-encryption.org/v2\n".to_vec();
+        expected.extend_from_slice(&[7u8; 32]);
+        assert_eq!(header.to_bytes(), &expected[..]);
+        assert_eq!(header.encoded_len(), HEADER_LEN);
+        assert_eq!(Header::read_from(&expected[..]).expect("Header should be valid, but"), header);
+    }
+
+    #[test]
+    fn header_does_not_consume_payload() {
+        let mut buf = v1_header([7u8; 32]);
+        buf.extend_from_slice(b"payload");
+        let mut reader = &buf[..];
+
+        let header = Header::read_from(&mut reader).expect("Header should be valid, but");
+        assert_eq!(header.version, Version::V1);
+        assert_eq!(header.ephemeral_identity, PublicKey::from([7u8; 32]));
+        assert_eq!(reader, b"payload");
+    }
+
+    #[test]
+    fn header_missing_prefix() {
+        assert_matches!(Header::read_from(&b"age-encryption.org/v1\n"[..]), Err(HeaderError::MissingPrefix));
+        assert_matches!(Header::read_from(&b"zorn-encryption.org\n"[..]), Err(HeaderError::MissingPrefix));
+    }
+
+    #[test]
+    fn header_invalid_version() {
+        assert_matches!(Header::read_from(&b"zorn-encryption.org/v 1\n"[..]), Err(HeaderError::InvalidVersion(b' ')));
+        assert_matches!(Header::read_from(&b"zorn-encryption.org/\n"[..]), Err(HeaderError::InvalidVersion(b'\n')));
+        assert_matches!(Header::read_from(&b"zorn-encryption.org/v1\r\n"[..]), Err(HeaderError::InvalidVersion(b'\r')));
+    }
+
+    #[test]
+    fn header_unknown_version() {
+        assert_matches!(Header::read_from(&b"zorn-encryption.org/v3\n"[..]), Err(HeaderError::UnknownVersion(v)) if v == "v3");
+        assert_matches!(Header::read_from(&b"zorn-encryption.org/v1p\n"[..]), Err(HeaderError::UnknownVersion(v)) if v == "v1p");
+        assert_matches!(Header::read_from(&[b"zorn-encryption.org/".as_slice(), &[b'v'; 1024]].concat()[..]), Err(HeaderError::UnknownVersion(_)));
+    }
+
+    #[test]
+    fn header_truncated() {
+        let buf = v1_header([7u8; 32]);
+        for len in 0..buf.len() {
+            assert_matches!(Header::read_from(&buf[..len]), Err(HeaderError::Truncated));
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn header_roundtrip(ephemeral_identity in any::<[u8; 32]>(), payload in vec(any::<u8>(), 0..=64)) {
+            let mut buf = Vec::new();
+            let header = Header::new(PublicKey::from(ephemeral_identity));
+            header.write_to(&mut buf).expect("Writing to a Vec should succeed, but");
+            buf.extend_from_slice(&payload);
+
+            let mut reader = &buf[..];
+            assert_eq!(header, Header::read_from(&mut reader).expect("Header should be valid, but"));
+            assert_eq!(reader, &payload[..]);
+        }
+    }
+}
@@ -1,7 +1,11 @@
-use std::ops::{Deref, DerefMut};
+use core::ops::{Deref, DerefMut};
+#[cfg(feature = "std")]
+use std::fmt;
+#[cfg(feature = "std")]
 use bech32::{ToBase32, FromBase32};
 use rand_core::{RngCore, CryptoRng};
-use zeroize::Zeroize;
+use zeroize::{Zeroize, Zeroizing};
+#[cfg(feature = "std")]
 use thiserror::Error;
 
 use x25519_dalek::{PublicKey, StaticSecret, SharedSecret};
@@ -9,10 +13,28 @@ use x25519_dalek::{PublicKey, StaticSecret, SharedSecret};
 #[cfg(test)]
 use proptest::{arbitrary::Arbitrary, strategy::{BoxedStrategy, Strategy}};
 
-//const ZORN_SECRET_APPLICATION_CONTEXT: &str = "zorn-encryption.org/cli 2022-08-28T15:31:50+00:00 ZornIdentitySecret key derivation";
+const ZORN_SECRET_APPLICATION_CONTEXT: &str = "zorn-encryption.org/v1 cli 2022-08-28T15:31:50+00:00 ZornIdentitySecret key derivation";
+#[cfg(feature = "std")]
 const ZORN_IDENTITY_HRP: &str = "zornv1-";
+#[cfg(feature = "std")]
+const ZORN_SECRET_KEY_HRP: &str = "zornv1-secret-key-";
+// Master secrets are meant to outlive format versions, so their HRP is unversioned
+#[cfg(feature = "std")]
+const ZORN_MASTER_SECRET_HRP: &str = "zorn-master-secret-";
 
-#[derive(Debug, PartialEq, Eq)]
+// Encodings of the points of order 1, 2, 4 and 8 on Curve25519, including the
+// non-canonical encodings of 0 and 1. Bit 255 is ignored when comparing.
+pub(crate) const LOW_ORDER_POINTS: [[u8; 32]; 7] = [
+    hex_literal::hex!("0000000000000000000000000000000000000000000000000000000000000000"),
+    hex_literal::hex!("0100000000000000000000000000000000000000000000000000000000000000"),
+    hex_literal::hex!("e0eb7a7c3b41b8ae1656e3faf19fc46ada098deb9c32b1fd866205165f49b800"),
+    hex_literal::hex!("5f9c95bca3508c24b1d0b1559c83ef5b04445cc4581c8e86d8224eddd09f1157"),
+    hex_literal::hex!("ecffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff7f"),
+    hex_literal::hex!("edffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff7f"),
+    hex_literal::hex!("eeffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff7f"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ZornIdentity(pub(crate) PublicKey);
 
 impl Deref for ZornIdentity {
@@ -30,12 +52,37 @@ impl DerefMut for ZornIdentity {
 }
 
 impl ZornIdentity {
+    #[cfg(feature = "std")]
     pub fn to_string(&self) -> String {
         bech32::encode(ZORN_IDENTITY_HRP, self.to_bytes().to_base32(), bech32::Variant::Bech32m)
             .expect("The HRP is valid")
     }
+
+    pub fn has_low_order(&self) -> bool {
+        let mut bytes = self.to_bytes();
+        bytes[31] &= 0x7f;
+        LOW_ORDER_POINTS.contains(&bytes)
+    }
+
+    // Like from_str, but additionally rejects identities no key exchange could
+    // ever succeed with
+    #[cfg(feature = "std")]
+    pub fn from_str_strict(s: &str) -> Result<Self, ZornIdentityDecodeError> {
+        let id: ZornIdentity = s.parse()?;
+        if id.has_low_order() {
+            return Err(ZornIdentityDecodeError::LowOrderPoint);
+        }
+        Ok(id)
+    }
 }
 
+impl From<[u8; 32]> for ZornIdentity {
+    fn from(bytes: [u8; 32]) -> Self {
+        ZornIdentity(PublicKey::from(bytes))
+    }
+}
+
+#[cfg(feature = "std")]
 #[derive(Error, Debug, PartialEq, Eq)]
 pub enum ZornIdentityDecodeError {
     #[error("incorrect byte length {0} for a public key")]
@@ -44,10 +91,13 @@ pub enum ZornIdentityDecodeError {
     IncorrectHRP,
     #[error("string is Bech32 instead of Bech32m")]
     IncorrectBech32Variant,
+    #[error("identity is a low order point")]
+    LowOrderPoint,
     #[error(transparent)]
     InvalidBech32mEncoding(#[from] bech32::Error),
 }
 
+#[cfg(feature = "std")]
 impl std::str::FromStr for ZornIdentity {
     type Err = ZornIdentityDecodeError;
 
@@ -88,29 +138,177 @@ impl From<&ZornIdentitySecret> for ZornIdentity {
     }
 }
 
+impl From<[u8; 32]> for ZornIdentitySecret {
+    fn from(bytes: [u8; 32]) -> Self {
+        ZornIdentitySecret(StaticSecret::from(bytes))
+    }
+}
+
+#[cfg(feature = "std")]
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ZornIdentitySecretDecodeError {
+    #[error("incorrect byte length {0} for a secret key")]
+    IncorrectSecretKeyLength(usize),
+    #[error("string has an incorrect HRP for a zornv1 secret key")]
+    IncorrectHRP,
+    #[error("string is Bech32 instead of Bech32m")]
+    IncorrectBech32Variant,
+    #[error(transparent)]
+    InvalidBech32mEncoding(#[from] bech32::Error),
+}
+
+#[cfg(feature = "std")]
+impl std::str::FromStr for ZornIdentitySecret {
+    type Err = ZornIdentitySecretDecodeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        #[cfg(fuzzing)] let (hrp, data32, variant) = bech32::decode_without_checksum(s)
+            .map(|(hrp, data32)| (hrp, data32, bech32::Variant::Bech32m))?;
+
+        #[cfg(not(fuzzing))] let (hrp, data32, variant) = bech32::decode(s)?;
+
+        let data = Zeroizing::new(match (hrp.as_str(), variant) {
+            (ZORN_SECRET_KEY_HRP, bech32::Variant::Bech32m) => Vec::from_base32(&data32).map_err(ZornIdentitySecretDecodeError::from),
+            (ZORN_SECRET_KEY_HRP, _) => Err(ZornIdentitySecretDecodeError::IncorrectBech32Variant),
+            (&_, _) => Err(ZornIdentitySecretDecodeError::IncorrectHRP),
+        }?);
+        TryInto::<[u8; 32]>::try_into(&data[..])
+            .map_err(|_| ZornIdentitySecretDecodeError::IncorrectSecretKeyLength(data.len()))
+            .map(|sk| ZornIdentitySecret(StaticSecret::from(sk)))
+    }
+}
+
+// Secret keys are written in upper case to set them apart from identities
+#[cfg(feature = "std")]
+impl fmt::Display for ZornIdentitySecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sk = Zeroizing::new(self.0.to_bytes());
+        let mut encoded = Zeroizing::new(bech32::encode(ZORN_SECRET_KEY_HRP, sk.to_base32(), bech32::Variant::Bech32m)
+            .expect("The HRP is valid"));
+        encoded.make_ascii_uppercase();
+        f.write_str(&encoded)
+    }
+}
+
 impl ZornIdentitySecret {
     pub fn new<T: RngCore + CryptoRng>(csprng: T) -> ZornIdentitySecret {
         ZornIdentitySecret(StaticSecret::new(csprng))
     }
 
+    // Like new, but returns an error instead of panicking when the csprng fails
+    pub fn try_new<T: RngCore + CryptoRng>(mut csprng: T) -> Result<ZornIdentitySecret, rand_core::Error> {
+        let mut bytes = Zeroizing::new([0u8; 32]);
+        csprng.try_fill_bytes(&mut *bytes)?;
+        Ok(ZornIdentitySecret(StaticSecret::from(*bytes)))
+    }
+
+    // The label separates identities derived from the same master secret; an
+    // empty label is the same as no label
+    pub fn derive(master_secret: &ZornMasterSecret, label: Option<&str>) -> ZornIdentitySecret {
+        let mut hasher = blake3::Hasher::new_derive_key(ZORN_SECRET_APPLICATION_CONTEXT);
+        hasher.update(&master_secret.0);
+        hasher.update(label.unwrap_or_default().as_bytes());
+        ZornIdentitySecret(StaticSecret::from(*Zeroizing::new(<[u8; 32]>::from(hasher.finalize()))))
+    }
+
     pub fn diffie_hellman(&self, their_id: &PublicKey) -> SharedSecret {
         self.0.diffie_hellman(their_id)
     }
 }
 
-#[cfg(test)]
+#[derive(Zeroize)]
+#[zeroize(drop)]
+pub struct ZornMasterSecret([u8; 32]);
+
+#[cfg(test)] opaque_debug::implement!(ZornMasterSecret);
+#[cfg(test)] impl Arbitrary for ZornMasterSecret {
+    type Parameters = <u8 as Arbitrary>::Parameters;
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(args: Self::Parameters) -> Self::Strategy {
+        <[u8; 32] as Arbitrary>::arbitrary_with(args).prop_map(ZornMasterSecret).boxed()
+    }
+}
+
+impl ZornMasterSecret {
+    pub fn new<T: RngCore + CryptoRng>(mut csprng: T) -> ZornMasterSecret {
+        let mut bytes = [0u8; 32];
+        csprng.fill_bytes(&mut bytes);
+        ZornMasterSecret(bytes)
+    }
+
+    pub fn try_new<T: RngCore + CryptoRng>(mut csprng: T) -> Result<ZornMasterSecret, rand_core::Error> {
+        let mut master_secret = ZornMasterSecret([0u8; 32]);
+        csprng.try_fill_bytes(&mut master_secret.0)?;
+        Ok(master_secret)
+    }
+}
+
+impl From<[u8; 32]> for ZornMasterSecret {
+    fn from(bytes: [u8; 32]) -> Self {
+        ZornMasterSecret(bytes)
+    }
+}
+
+#[cfg(feature = "std")]
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ZornMasterSecretDecodeError {
+    #[error("incorrect byte length {0} for a master secret")]
+    IncorrectMasterSecretLength(usize),
+    #[error("string has an incorrect HRP for a zorn master secret")]
+    IncorrectHRP,
+    #[error("string is Bech32 instead of Bech32m")]
+    IncorrectBech32Variant,
+    #[error(transparent)]
+    InvalidBech32mEncoding(#[from] bech32::Error),
+}
+
+#[cfg(feature = "std")]
+impl std::str::FromStr for ZornMasterSecret {
+    type Err = ZornMasterSecretDecodeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        #[cfg(fuzzing)] let (hrp, data32, variant) = bech32::decode_without_checksum(s)
+            .map(|(hrp, data32)| (hrp, data32, bech32::Variant::Bech32m))?;
+
+        #[cfg(not(fuzzing))] let (hrp, data32, variant) = bech32::decode(s)?;
+
+        let data = Zeroizing::new(match (hrp.as_str(), variant) {
+            (ZORN_MASTER_SECRET_HRP, bech32::Variant::Bech32m) => Vec::from_base32(&data32).map_err(ZornMasterSecretDecodeError::from),
+            (ZORN_MASTER_SECRET_HRP, _) => Err(ZornMasterSecretDecodeError::IncorrectBech32Variant),
+            (&_, _) => Err(ZornMasterSecretDecodeError::IncorrectHRP),
+        }?);
+        TryInto::<[u8; 32]>::try_into(&data[..])
+            .map_err(|_| ZornMasterSecretDecodeError::IncorrectMasterSecretLength(data.len()))
+            .map(ZornMasterSecret)
+    }
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for ZornMasterSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut encoded = Zeroizing::new(bech32::encode(ZORN_MASTER_SECRET_HRP, self.0.to_base32(), bech32::Variant::Bech32m)
+            .expect("The HRP is valid"));
+        encoded.make_ascii_uppercase();
+        f.write_str(&encoded)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use std::str::FromStr;
 
+    use bech32::ToBase32;
     use hex_literal::hex;
-    use x25519_dalek::StaticSecret;
+    use x25519_dalek::{PublicKey, StaticSecret};
 
-    use crate::identity::{ZornIdentity, ZornIdentitySecret, ZornIdentityDecodeError};
+    use crate::identity::{LOW_ORDER_POINTS, ZornIdentity, ZornIdentitySecret, ZornIdentityDecodeError, ZornIdentitySecretDecodeError, ZornMasterSecret, ZornMasterSecretDecodeError};
 
     use proptest::{proptest, prelude::any};
 
     const TEST_SK: [u8; 32] = hex!("00b575f5689a44612c4c8b4f6fb257623bd24b53838b10a50e84ef340bed057d");
     const TEST_ID: &str = "zornv1-1gjfs6r7x5fmydhgrz9cnwrdkdnnvt3w7zhwya6dwvrp528qjmd3s04fc4w";
+    const TEST_SK_ENCODED: &str = "ZORNV1-SECRET-KEY-1QZ6HTATGNFZXZTZV3D8KLVJHVGAAYJ6NSW93PFGWSNHNGZLDQ47S2M8FP2";
 
     #[test]
     fn zorn_identity_bech32m_test_vector() {
@@ -143,7 +341,90 @@ mod tests {
             Err(ZornIdentityDecodeError::InvalidBech32mEncoding(bech32::Error::InvalidChecksum)));
     }
 
+    #[test]
+    fn zorn_identity_strict_rejects_low_order() {
+        assert_eq!(ZornIdentity::from_str_strict(TEST_ID).map(|id| id.to_string()), Ok(TEST_ID.to_owned()));
+
+        for mut low_order in LOW_ORDER_POINTS {
+            for high_bit in [0x00, 0x80] {
+                low_order[31] |= high_bit;
+                let encoded = ZornIdentity(PublicKey::from(low_order)).to_string();
+                assert!(ZornIdentity::from_str(&encoded).is_ok());
+                assert_eq!(ZornIdentity::from_str_strict(&encoded), Err(ZornIdentityDecodeError::LowOrderPoint));
+            }
+        }
+    }
+
+    #[test]
+    fn zorn_identity_secret_bech32m_test_vector() {
+        let sk = ZornIdentitySecret(StaticSecret::from(TEST_SK));
+        assert_eq!(sk.to_string(), TEST_SK_ENCODED);
+        let decoded = ZornIdentitySecret::from_str(TEST_SK_ENCODED).expect("TEST_SK_ENCODED should be valid, but");
+        assert_eq!(decoded.0.to_bytes(), TEST_SK);
+        let decoded = ZornIdentitySecret::from_str(&TEST_SK_ENCODED.to_lowercase()).expect("Lower case encoding should be valid, but");
+        assert_eq!(decoded.0.to_bytes(), TEST_SK);
+    }
+
+    #[test]
+    fn zorn_identity_secret_bech32m_invalid_hrp() {
+        assert_eq!(ZornIdentitySecret::from_str(TEST_ID).map(|_| ()), Err(ZornIdentitySecretDecodeError::IncorrectHRP));
+    }
+
+    #[test]
+    fn zorn_identity_secret_bech32m_incorrect_length() {
+        let encoded = bech32::encode("zornv1-secret-key-", [0u8; 31].to_base32(), bech32::Variant::Bech32m).expect("Encoding should be valid, but");
+        assert_eq!(ZornIdentitySecret::from_str(&encoded).map(|_| ()), Err(ZornIdentitySecretDecodeError::IncorrectSecretKeyLength(31)));
+    }
+
+    #[test]
+    fn zorn_identity_secret_bech32m_incorrect_variant() {
+        let encoded = bech32::encode("zornv1-secret-key-", TEST_SK.to_base32(), bech32::Variant::Bech32).expect("Encoding should be valid, but");
+        assert_eq!(ZornIdentitySecret::from_str(&encoded).map(|_| ()), Err(ZornIdentitySecretDecodeError::IncorrectBech32Variant));
+    }
+
+    #[test]
+    fn zorn_identity_secret_bech32m_mixed_case() {
+        let mixed = TEST_SK_ENCODED.replacen('Z', "z", 1);
+        assert_eq!(ZornIdentitySecret::from_str(&mixed).map(|_| ()),
+            Err(ZornIdentitySecretDecodeError::InvalidBech32mEncoding(bech32::Error::MixedCase)));
+    }
+
+    #[test]
+    fn zorn_master_secret_derivation_test_vector() {
+        let master = ZornMasterSecret::from(hex!("6d61737465722073656372657420666f72207a6f726e2074657374696e672121"));
+        assert_eq!(master.to_string(), "ZORN-MASTER-SECRET-1D4SHXAR9WGS8XETRWFJHGGRXDAEZQ7N0WFHZQAR9WD6XJMN8YYSS9X372C");
+        assert_eq!(ZornIdentity::from(&ZornIdentitySecret::derive(&master, None)).to_string(), "zornv1-12e7mg73e5y5afwkhzxe000523hay2pwtaydsx7c6rklc445weyvqg6skze");
+        assert_eq!(ZornIdentity::from(&ZornIdentitySecret::derive(&master, Some("backup"))).to_string(), "zornv1-1m5ujzd4sza9z7purqt8y3q8y72whr9zua6qxee2xas4uxdqyvvyslhk4pw");
+    }
+
+    #[test]
+    fn zorn_master_secret_bech32m_invalid_hrp() {
+        assert_eq!(ZornMasterSecret::from_str(TEST_SK_ENCODED).map(|_| ()), Err(ZornMasterSecretDecodeError::IncorrectHRP));
+    }
+
     proptest! {
+        #[test]
+        fn zorn_master_secret_bech32m_roundtrip(master in any::<ZornMasterSecret>()) {
+            let decoded = ZornMasterSecret::from_str(master.to_string().as_str()).expect("Encoding should be valid, but");
+            assert_eq!(master.0, decoded.0);
+        }
+
+        #[test]
+        fn zorn_master_secret_labels_separate_identities(master in any::<ZornMasterSecret>(), label in "[a-z]{1,16}") {
+            let unlabeled = ZornIdentity::from(&ZornIdentitySecret::derive(&master, None));
+            assert_eq!(unlabeled, ZornIdentity::from(&ZornIdentitySecret::derive(&master, Some(""))));
+            assert_ne!(unlabeled, ZornIdentity::from(&ZornIdentitySecret::derive(&master, Some(&label))));
+            assert_eq!(
+                ZornIdentity::from(&ZornIdentitySecret::derive(&master, Some(&label))),
+                ZornIdentity::from(&ZornIdentitySecret::derive(&master, Some(&label))));
+        }
+
+        #[test]
+        fn zorn_identity_secret_bech32m_roundtrip(sk in any::<ZornIdentitySecret>()) {
+            let decoded = ZornIdentitySecret::from_str(sk.to_string().as_str()).expect("Encoding should be valid, but");
+            assert_eq!(sk.0.to_bytes(), decoded.0.to_bytes());
+        }
+
         #[test]
         fn zorn_identity_bech32m_roundtrip(sk in any::<ZornIdentitySecret>()) {
             let id = ZornIdentity::from(&sk);
@@ -0,0 +1,186 @@
+use std::io::{self, BufRead, Write};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use crate::identity::{ZornIdentity, ZornIdentitySecret, ZornIdentitySecretDecodeError, ZornMasterSecret, ZornMasterSecretDecodeError};
+
+// An identity file holds one secret key per line. Empty lines and lines
+// starting with `#` are ignored.
+pub struct IdentityFile {
+    identities: Vec<ZornIdentitySecret>,
+}
+
+#[derive(Error, Debug)]
+pub enum IdentityFileError {
+    #[error("invalid secret key on line {line}: {source}")]
+    InvalidSecretKey { line: usize, source: ZornIdentitySecretDecodeError },
+    #[error("invalid master secret on line {line}: {source}")]
+    InvalidMasterSecret { line: usize, source: ZornMasterSecretDecodeError },
+    #[error("master secret file contains more than one master secret")]
+    MultipleMasterSecrets,
+    #[error("line {0} is not valid UTF-8")]
+    InvalidUtf8(usize),
+    #[error("file contains no secret keys")]
+    NoSecretKeys,
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+// Calls f with the line number and contents of every line holding a key
+fn for_each_key<R: BufRead>(mut reader: R, mut f: impl FnMut(usize, &str) -> Result<(), IdentityFileError>) -> Result<(), IdentityFileError> {
+    let mut buf = Zeroizing::new(Vec::new());
+
+    for line in 1.. {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+
+        let s = std::str::from_utf8(&buf).map_err(|_| IdentityFileError::InvalidUtf8(line))?.trim();
+        if s.is_empty() || s.starts_with('#') {
+            continue;
+        }
+        f(line, s)?;
+    }
+    Ok(())
+}
+
+// A master secret file follows the identity file syntax, but holds exactly one master secret
+pub fn read_master_secret<R: BufRead>(reader: R) -> Result<ZornMasterSecret, IdentityFileError> {
+    let mut master_secret = None;
+    for_each_key(reader, |line, s| {
+        let parsed = s.parse().map_err(|source| IdentityFileError::InvalidMasterSecret { line, source })?;
+        match master_secret.replace(parsed) {
+            Some(_) => Err(IdentityFileError::MultipleMasterSecrets),
+            None => Ok(()),
+        }
+    })?;
+    master_secret.ok_or(IdentityFileError::NoSecretKeys)
+}
+
+pub fn write_master_secret<W: Write>(mut writer: W, master_secret: &ZornMasterSecret, created: Option<&str>) -> io::Result<()> {
+    if let Some(created) = created {
+        writeln!(writer, "# created: {}", created)?;
+    }
+    writeln!(writer, "# master secret for deriving zorn identities")?;
+    writeln!(writer, "{}", master_secret)
+}
+
+impl IdentityFile {
+    pub fn new(identities: Vec<ZornIdentitySecret>) -> Self {
+        IdentityFile { identities }
+    }
+
+    pub fn identities(&self) -> &[ZornIdentitySecret] {
+        &self.identities
+    }
+
+    pub fn into_identities(self) -> Vec<ZornIdentitySecret> {
+        self.identities
+    }
+
+    pub fn read_from<R: BufRead>(reader: R) -> Result<Self, IdentityFileError> {
+        let mut identities = Vec::new();
+        for_each_key(reader, |line, s| {
+            identities.push(s.parse().map_err(|source| IdentityFileError::InvalidSecretKey { line, source })?);
+            Ok(())
+        })?;
+
+        if identities.is_empty() {
+            return Err(IdentityFileError::NoSecretKeys);
+        }
+        Ok(IdentityFile { identities })
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W, created: Option<&str>) -> io::Result<()> {
+        for secret in &self.identities {
+            if let Some(created) = created {
+                writeln!(writer, "# created: {}", created)?;
+            }
+            writeln!(writer, "# public identity: {}", ZornIdentity::from(secret).to_string())?;
+            writeln!(writer, "{}", secret)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::assert_matches::assert_matches;
+    use indoc::indoc;
+
+    use crate::identity::{ZornIdentity, ZornIdentitySecret, ZornIdentitySecretDecodeError, ZornMasterSecret};
+
+    use super::{IdentityFile, IdentityFileError, read_master_secret, write_master_secret};
+
+    use proptest::{proptest, prelude::any, collection::vec};
+
+    const TEST_FILE: &str = indoc! {"
+        # created: 2022-09-01T12:00:00Z
+        # public identity: zornv1-1gjfs6r7x5fmydhgrz9cnwrdkdnnvt3w7zhwya6dwvrp528qjmd3s04fc4w
+        ZORNV1-SECRET-KEY-1QZ6HTATGNFZXZTZV3D8KLVJHVGAAYJ6NSW93PFGWSNHNGZLDQ47S2M8FP2
+
+    "};
+
+    fn read(contents: &[u8]) -> Result<(), IdentityFileError> {
+        IdentityFile::read_from(contents).map(|_| ())
+    }
+
+    #[test]
+    fn identity_file_test_vector() {
+        let file = IdentityFile::read_from(TEST_FILE.as_bytes()).expect("TEST_FILE should be valid, but");
+        assert_eq!(file.identities().len(), 1);
+        assert_eq!(ZornIdentity::from(&file.identities()[0]).to_string(), "zornv1-1gjfs6r7x5fmydhgrz9cnwrdkdnnvt3w7zhwya6dwvrp528qjmd3s04fc4w");
+
+        let mut written = Vec::new();
+        file.write_to(&mut written, Some("2022-09-01T12:00:00Z")).expect("Writing to a Vec should succeed, but");
+        assert_eq!(written, TEST_FILE.trim_end().as_bytes().iter().chain(b"\n").copied().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn identity_file_invalid_line() {
+        let contents = TEST_FILE.replace("2M8FP2", "2M8FP3");
+        assert_matches!(read(contents.as_bytes()),
+            Err(IdentityFileError::InvalidSecretKey { line: 3, source: ZornIdentitySecretDecodeError::InvalidBech32mEncoding(bech32::Error::InvalidChecksum) }));
+
+        let contents = TEST_FILE.replace("# public", "public");
+        assert_matches!(read(contents.as_bytes()),
+            Err(IdentityFileError::InvalidSecretKey { line: 2, .. }));
+
+        assert_matches!(read(&b"# comment\n\xff\n"[..]), Err(IdentityFileError::InvalidUtf8(2)));
+    }
+
+    #[test]
+    fn identity_file_without_keys() {
+        assert_matches!(read(&b""[..]), Err(IdentityFileError::NoSecretKeys));
+        assert_matches!(read(&b"# created: never\n\n"[..]), Err(IdentityFileError::NoSecretKeys));
+    }
+
+    #[test]
+    fn master_secret_file() {
+        let master_secret = ZornMasterSecret::from([42u8; 32]);
+        let mut written = Vec::new();
+        write_master_secret(&mut written, &master_secret, Some("2022-09-01T12:00:00Z")).expect("Writing to a Vec should succeed, but");
+
+        let read = read_master_secret(&written[..]).expect("Written file should be valid, but");
+        assert_eq!(read.to_string(), master_secret.to_string());
+
+        assert_matches!(read_master_secret(TEST_FILE.as_bytes()).map(|_| ()), Err(IdentityFileError::InvalidMasterSecret { line: 3, .. }));
+        assert_matches!(read_master_secret(&[&written[..], &written[..]].concat()[..]).map(|_| ()), Err(IdentityFileError::MultipleMasterSecrets));
+        assert_matches!(read_master_secret(&b"# nothing here\n"[..]).map(|_| ()), Err(IdentityFileError::NoSecretKeys));
+    }
+
+    proptest! {
+        #[test]
+        fn identity_file_roundtrip(secrets in vec(any::<ZornIdentitySecret>(), 1..=4)) {
+            let mut written = Vec::new();
+            IdentityFile::new(secrets.iter().map(|s| s.to_string().parse().expect("Encoding should be valid, but")).collect())
+                .write_to(&mut written, None).expect("Writing to a Vec should succeed, but");
+
+            let file = IdentityFile::read_from(&written[..]).expect("Written file should be valid, but");
+            assert_eq!(
+                file.identities().iter().map(ZornIdentity::from).collect::<Vec<_>>(),
+                secrets.iter().map(ZornIdentity::from).collect::<Vec<_>>());
+        }
+    }
+}
@@ -1,6 +1,11 @@
+#[cfg(feature = "std")]
 use rand_core::OsRng;
-use x25519_dalek::{EphemeralSecret, PublicKey};
-use zeroize::Zeroize;
+use curve25519_dalek::constants::{ED25519_BASEPOINT_TABLE, EIGHT_TORSION};
+use curve25519_dalek::scalar::Scalar;
+use rand_core::{CryptoRng, RngCore};
+use thiserror::Error;
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+use zeroize::{Zeroize, Zeroizing};
 
 use crate::identity::{ZornIdentity, ZornIdentitySecret};
 
@@ -10,51 +15,124 @@ pub struct SharedSecret([u8; 32]);
 
 const KEY_EXCHANGE_CONTEXT: &str = "zorn-encryption.org/v1 shared secret";
 
-fn generate_ephemeral_identity() -> (EphemeralSecret, PublicKey) {
-    let s = EphemeralSecret::new(OsRng);
+#[derive(Error, Debug)]
+pub enum KeyExchangeError {
+    #[error("key exchange with a low order identity is not contributory")]
+    NonContributory,
+    #[error("random number generator failed: {0}")]
+    Rng(rand_core::Error),
+}
+
+// A low order identity would force the Diffie-Hellman output to the identity
+// point, which anyone can compute
+#[cfg(feature = "std")]
+impl From<KeyExchangeError> for std::io::Error {
+    fn from(e: KeyExchangeError) -> Self {
+        match e {
+            KeyExchangeError::NonContributory => std::io::Error::new(std::io::ErrorKind::InvalidInput, e),
+            KeyExchangeError::Rng(_) => std::io::Error::other(e),
+        }
+    }
+}
+
+fn contributory(dh: x25519_dalek::SharedSecret) -> Result<x25519_dalek::SharedSecret, KeyExchangeError> {
+    if dh.was_contributory() {
+        Ok(dh)
+    } else {
+        Err(KeyExchangeError::NonContributory)
+    }
+}
+
+fn generate_ephemeral_identity<T: RngCore + CryptoRng>(csprng: T) -> Result<(EphemeralSecret, PublicKey), KeyExchangeError> {
+    let s = EphemeralSecret::try_new(csprng).map_err(KeyExchangeError::Rng)?;
     let pk = PublicKey::from(&s);
-    (s, pk)
+    Ok((s, pk))
+}
+
+// The ephemeral identity of a uniform message is a random point on the whole
+// curve with an Elligator2 representative. Without the random low order
+// component it would always lie in the prime order subgroup, which is easy to
+// test for. X25519 clamps scalars to multiples of the cofactor, so the
+// component drops out of every Diffie-Hellman output again.
+fn generate_uniform_ephemeral_identity<T: RngCore + CryptoRng>(mut csprng: T) -> Result<(StaticSecret, PublicKey, [u8; 32]), KeyExchangeError> {
+    loop {
+        let mut secret = Zeroizing::new([0u8; 32]);
+        let mut tweak = [0u8; 2];
+        csprng.try_fill_bytes(&mut secret[..]).map_err(KeyExchangeError::Rng)?;
+        csprng.try_fill_bytes(&mut tweak).map_err(KeyExchangeError::Rng)?;
+
+        let ephemeral_secret = StaticSecret::from(*secret);
+        let scalar = Zeroizing::new(Scalar::from_bits(ephemeral_secret.to_bytes()));
+        let point = (&ED25519_BASEPOINT_TABLE * &*scalar + EIGHT_TORSION[usize::from(tweak[0] % 8)]).to_montgomery();
+        if let Some(representative) = point.to_representative(tweak[1]) {
+            return Ok((ephemeral_secret, PublicKey::from(point.to_bytes()), representative));
+        }
+    }
 }
 
-fn compute_sender_shared_secret(sender_secret: &ZornIdentitySecret, ephemeral_secret: EphemeralSecret, ephemeral_identity: &PublicKey, recipient_identity: &ZornIdentity) -> SharedSecret {
+fn compute_sender_shared_secret(sender_secret: &ZornIdentitySecret, ephemeral_shared: x25519_dalek::SharedSecret, ephemeral_identity: &PublicKey, recipient_identity: &ZornIdentity) -> Result<SharedSecret, KeyExchangeError> {
     let mut hasher = blake3::Hasher::new_derive_key(KEY_EXCHANGE_CONTEXT);
 
-    hasher.update(sender_secret.diffie_hellman(recipient_identity).as_bytes());
-    hasher.update(ephemeral_secret.diffie_hellman(recipient_identity).as_bytes());
+    hasher.update(contributory(sender_secret.diffie_hellman(recipient_identity))?.as_bytes());
+    hasher.update(contributory(ephemeral_shared)?.as_bytes());
     hasher.update(ephemeral_identity.as_bytes());
     hasher.update(ZornIdentity::from(sender_secret).as_bytes());
     hasher.update(recipient_identity.as_bytes());
 
-    SharedSecret(hasher.finalize().into())
+    Ok(SharedSecret(hasher.finalize().into()))
 }
 
 impl SharedSecret {
-    pub fn sender(sender_secret: &ZornIdentitySecret, recipient_identity: &ZornIdentity) -> (PublicKey, Self) {
-        let (ephemeral_secret, ephemeral_identity) = generate_ephemeral_identity();
-        (ephemeral_identity, compute_sender_shared_secret(sender_secret, ephemeral_secret, &ephemeral_identity, recipient_identity))
+    #[cfg(feature = "std")]
+    pub fn sender(sender_secret: &ZornIdentitySecret, recipient_identity: &ZornIdentity) -> Result<(PublicKey, Self), KeyExchangeError> {
+        Self::sender_with_rng(OsRng, sender_secret, recipient_identity)
+    }
+
+    pub fn sender_with_rng<T: RngCore + CryptoRng>(csprng: T, sender_secret: &ZornIdentitySecret, recipient_identity: &ZornIdentity) -> Result<(PublicKey, Self), KeyExchangeError> {
+        let (ephemeral_secret, ephemeral_identity) = generate_ephemeral_identity(csprng)?;
+        Ok((ephemeral_identity, compute_sender_shared_secret(sender_secret, ephemeral_secret.diffie_hellman(recipient_identity), &ephemeral_identity, recipient_identity)?))
+    }
+
+    // Also returns the representative of the ephemeral identity, which stands
+    // in for it in a uniform message
+    pub fn sender_uniform_with_rng<T: RngCore + CryptoRng>(csprng: T, sender_secret: &ZornIdentitySecret, recipient_identity: &ZornIdentity) -> Result<([u8; 32], PublicKey, Self), KeyExchangeError> {
+        let (ephemeral_secret, ephemeral_identity, representative) = generate_uniform_ephemeral_identity(csprng)?;
+        let shared_secret = compute_sender_shared_secret(sender_secret, ephemeral_secret.diffie_hellman(recipient_identity), &ephemeral_identity, recipient_identity)?;
+        Ok((representative, ephemeral_identity, shared_secret))
     }
 
-    pub fn recipient(recipient_secret: &ZornIdentitySecret, sender_identity: &ZornIdentity, ephemeral_identity: &PublicKey) -> Self {
+    pub fn recipient(recipient_secret: &ZornIdentitySecret, sender_identity: &ZornIdentity, ephemeral_identity: &PublicKey) -> Result<Self, KeyExchangeError> {
         let mut hasher = blake3::Hasher::new_derive_key(KEY_EXCHANGE_CONTEXT);
     
-        hasher.update(recipient_secret.diffie_hellman(sender_identity).as_bytes());
-        hasher.update(recipient_secret.diffie_hellman(ephemeral_identity).as_bytes());
+        hasher.update(contributory(recipient_secret.diffie_hellman(sender_identity))?.as_bytes());
+        hasher.update(contributory(recipient_secret.diffie_hellman(ephemeral_identity))?.as_bytes());
         hasher.update(ephemeral_identity.as_bytes());
         hasher.update(sender_identity.as_bytes());
         hasher.update(ZornIdentity::from(recipient_secret).as_bytes());
     
-        SharedSecret(hasher.finalize().into())
+        Ok(SharedSecret(hasher.finalize().into()))
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
     }
 }
 
 
 #[cfg(test)]
 mod tests {
-    use crate::identity::{ZornIdentity, ZornIdentitySecret};
-    use rand_core::{RngCore, CryptoRng, impls, OsRng};
+    use std::assert_matches::assert_matches;
+    use std::num::NonZeroU32;
+
+    #[cfg(feature = "std")]
+    use crate::identity::LOW_ORDER_POINTS;
+    use crate::identity::{ZornIdentity, ZornIdentitySecret, ZornMasterSecret};
+    #[cfg(feature = "std")]
+    use rand_core::OsRng;
+    use rand_core::{RngCore, CryptoRng, impls};
     use x25519_dalek::{EphemeralSecret, PublicKey};
 
-    use super::{SharedSecret, compute_sender_shared_secret};
+    use super::{KeyExchangeError, SharedSecret, compute_sender_shared_secret};
 
     struct DummyRng(u64);
     impl RngCore for DummyRng {
@@ -76,6 +154,27 @@ mod tests {
     }
     impl CryptoRng for DummyRng {}
 
+    // Behaves like OsRng without an entropy source
+    struct FailingRng;
+    impl RngCore for FailingRng {
+        fn next_u64(&mut self) -> u64 {
+            panic!("FailingRng has no infallible interface")
+        }
+
+        fn next_u32(&mut self) -> u32 {
+            panic!("FailingRng has no infallible interface")
+        }
+
+        fn fill_bytes(&mut self, _: &mut [u8]) {
+            panic!("FailingRng has no infallible interface")
+        }
+
+        fn try_fill_bytes(&mut self, _: &mut [u8]) -> Result<(), rand_core::Error> {
+            Err(rand_core::Error::from(NonZeroU32::new(rand_core::Error::CUSTOM_START).expect("CUSTOM_START is not zero")))
+        }
+    }
+    impl CryptoRng for FailingRng {}
+
     #[test]
     fn sender_secret_test_vector() {
         let sender_secret = ZornIdentitySecret::new(DummyRng(0));
@@ -84,16 +183,56 @@ mod tests {
         let recipient_identity = ZornIdentity::from(&ZornIdentitySecret::new(DummyRng(0)));
         assert_eq!(
             hex_literal::hex!("66e20c24acbc3a8bb4d803c5bf17d8f9840a2f917cda8c5c7a5878494ddb6b93"),
-            compute_sender_shared_secret(&sender_secret, ephemeral_secret, &ephemeral_identity, &recipient_identity).0);
+            compute_sender_shared_secret(&sender_secret, ephemeral_secret.diffie_hellman(&recipient_identity), &ephemeral_identity, &recipient_identity).expect("Key exchange should be contributory, but").0);
     }
 
+    #[cfg(feature = "std")]
     #[test]
     fn sender_recipient_exchange() {
         let sender_secret = ZornIdentitySecret::new(OsRng);
         let recipient_secret = ZornIdentitySecret::new(OsRng);
 
-        let (pk, sender_shared) = SharedSecret::sender(&sender_secret, &ZornIdentity::from(&recipient_secret));
-        let recipient_shared = SharedSecret::recipient(&recipient_secret, &ZornIdentity::from(&sender_secret), &pk);
+        let (pk, sender_shared) = SharedSecret::sender(&sender_secret, &ZornIdentity::from(&recipient_secret)).expect("Key exchange should be contributory, but");
+        let recipient_shared = SharedSecret::recipient(&recipient_secret, &ZornIdentity::from(&sender_secret), &pk).expect("Key exchange should be contributory, but");
+        assert_eq!(sender_shared.0, recipient_shared.0);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn rejects_low_order_identities() {
+        let secret = ZornIdentitySecret::new(OsRng);
+        let identity = ZornIdentity::from(&ZornIdentitySecret::new(OsRng));
+
+        for low_order in LOW_ORDER_POINTS {
+            let low_order = PublicKey::from(low_order);
+            assert_matches!(SharedSecret::sender(&secret, &ZornIdentity(low_order)).map(|_| ()), Err(KeyExchangeError::NonContributory));
+            assert_matches!(SharedSecret::recipient(&secret, &ZornIdentity(low_order), &identity).map(|_| ()), Err(KeyExchangeError::NonContributory));
+            assert_matches!(SharedSecret::recipient(&secret, &identity, &low_order).map(|_| ()), Err(KeyExchangeError::NonContributory));
+        }
+    }
+
+    #[test]
+    fn sender_recipient_exchange_with_rng() {
+        let sender_secret = ZornIdentitySecret::new(DummyRng(1));
+        let recipient_secret = ZornIdentitySecret::new(DummyRng(2));
+
+        let (pk, sender_shared) = SharedSecret::sender_with_rng(DummyRng(3), &sender_secret, &ZornIdentity::from(&recipient_secret)).expect("Key exchange should be contributory, but");
+        let recipient_shared = SharedSecret::recipient(&recipient_secret, &ZornIdentity::from(&sender_secret), &pk).expect("Key exchange should be contributory, but");
         assert_eq!(sender_shared.0, recipient_shared.0);
+        assert_ne!(sender_shared.0, SharedSecret::recipient(&sender_secret, &ZornIdentity::from(&recipient_secret), &pk).expect("Key exchange should be contributory, but").0);
+    }
+
+    #[test]
+    fn fallible_key_generation() {
+        let secret = ZornIdentitySecret::new(DummyRng(1));
+        let identity = ZornIdentity::from(&ZornIdentitySecret::new(DummyRng(2)));
+
+        assert_matches!(SharedSecret::sender_with_rng(FailingRng, &secret, &identity).map(|_| ()), Err(KeyExchangeError::Rng(_)));
+        assert!(ZornIdentitySecret::try_new(FailingRng).is_err());
+        assert!(ZornMasterSecret::try_new(FailingRng).is_err());
+
+        let sk = ZornIdentitySecret::try_new(DummyRng(0)).expect("DummyRng should not fail, but");
+        assert_eq!(ZornIdentity::from(&sk), ZornIdentity::from(&ZornIdentitySecret::new(DummyRng(0))));
+        assert!(SharedSecret::sender_with_rng(DummyRng(0), &secret, &identity).is_ok());
     }
 }
@@ -1,6 +1,31 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 #![feature(generic_arg_infer)]
 #![feature(assert_matches)]
 #![feature(let_else)]
+#[cfg(feature = "async")]
+pub mod async_io;
+pub mod chunk;
+#[cfg(feature = "std")]
+pub mod error;
+pub mod header;
 pub mod key_exchange;
 pub mod identity;
+#[cfg(feature = "std")]
+pub mod identity_file;
+pub mod padding;
+#[cfg(feature = "parallel")]
+pub mod parallel;
+#[cfg(feature = "std")]
+pub mod seekable;
+#[cfg(feature = "std")]
+pub mod spool;
+#[cfg(feature = "std")]
+pub mod stream;
+#[cfg(feature = "std")]
+pub mod uniform;
+#[cfg(all(test, feature = "std"))]
+mod test_util;
 pub mod xchacha20_blake3;
+
+#[cfg(feature = "std")]
+pub use error::Error;
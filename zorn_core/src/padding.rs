@@ -0,0 +1,138 @@
+// Padding hides the exact length of a message. The plaintext is followed by
+// a marker octet and as many zeros as it takes to reach the padded length,
+// and every chunk holding any of that carries the padding flag in its nonce.
+// A decryptor can then strip the padding chunk by chunk, without looking
+// ahead, and the flag is authenticated like everything else about a chunk.
+// Padded messages have the version line v2, so that a decryptor that
+// predates padding rejects them as an unknown version rather than as
+// tampered with, and a v1 message never has padded chunks.
+#[cfg(feature = "std")]
+use crate::header::Version;
+
+pub const PADDING_MARKER: u8 = 0x80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Padding {
+    // Round up to a length with only a few significant bits
+    Padme,
+    // Pad every message to the same length, which it has to fit in
+    To(u64),
+}
+
+impl Padding {
+    // Length of the padded plaintext, including the marker
+    pub fn padded_len(&self, len: u64) -> Option<u64> {
+        let min = len.checked_add(1)?;
+        match *self {
+            Padding::Padme => padme(min),
+            Padding::To(size) => (size >= min).then_some(size),
+        }
+    }
+}
+
+// Padmé from Nikitin et al., "Reducing Metadata Leakage from Encrypted Files
+// and Communication with PURBs". A length L is rounded up so that only the
+// top floor(log2(log2(L))) + 1 bits are significant, which leaves
+// O(log log L) bits of information about it at an overhead of at most 12%.
+pub fn padme(len: u64) -> Option<u64> {
+    if len < 2 {
+        return Some(len);
+    }
+    let e = len.ilog2();
+    let s = e.ilog2() + 1;
+    let mask = (1u64 << (e - s)) - 1;
+    len.checked_add(mask).map(|len| len & !mask)
+}
+
+// Whether the chunks of a message may carry the padding flag
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PaddingRule {
+    Forbidden,
+    // Uniform messages have no version line to tell either way
+    Optional,
+    // The final chunk of a padded message is always padding
+    Required,
+}
+
+#[cfg(feature = "std")]
+impl From<Version> for PaddingRule {
+    fn from(version: Version) -> Self {
+        match version {
+            Version::V1 => PaddingRule::Forbidden,
+            Version::V2 => PaddingRule::Required,
+        }
+    }
+}
+
+// Writes len octets of padding, the marker followed by zeros
+#[cfg(feature = "std")]
+pub(crate) fn write_padding<W: std::io::Write>(writer: &mut W, len: u64) -> std::io::Result<()> {
+    use std::io::Read;
+
+    writer.write_all(&[PADDING_MARKER])?;
+    std::io::copy(&mut std::io::repeat(0).take(len - 1), writer)?;
+    Ok(())
+}
+
+// How much of the plaintext of a padded chunk is message data. That is
+// everything before the marker in the first padded chunk and nothing in the
+// ones after it, which have to be all zeros.
+#[cfg(feature = "std")]
+pub(crate) fn data_len(plaintext: &[u8], first: bool) -> Option<usize> {
+    match plaintext.iter().rposition(|&b| b != 0) {
+        Some(i) if first && plaintext[i] == PADDING_MARKER => Some(i),
+        None if !first => Some(0),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "std")]
+    use super::{data_len, PADDING_MARKER};
+    use super::{padme, Padding};
+
+    use proptest::proptest;
+
+    #[test]
+    fn padme_known_values() {
+        let expected = [(0, 0), (1, 1), (2, 2), (9, 10), (100, 104), (1000, 1024), (1025, 1088), (65537, 67584), (1 << 40, 1 << 40)];
+        for (len, padded) in expected {
+            assert_eq!(padme(len), Some(padded), "padme({})", len);
+        }
+        assert_eq!(padme(u64::MAX), None);
+    }
+
+    #[test]
+    fn padded_len_fits_the_marker() {
+        assert_eq!(Padding::Padme.padded_len(0), Some(1));
+        assert_eq!(Padding::Padme.padded_len(999), Some(1024));
+        assert_eq!(Padding::To(1024).padded_len(1023), Some(1024));
+        assert_eq!(Padding::To(1024).padded_len(1024), None);
+        assert_eq!(Padding::To(1024).padded_len(0), Some(1024));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn data_len_finds_the_marker() {
+        assert_eq!(data_len(&[1, 2, PADDING_MARKER, 0, 0], true), Some(2));
+        assert_eq!(data_len(&[PADDING_MARKER], true), Some(0));
+        assert_eq!(data_len(&[1, 2, PADDING_MARKER], true), Some(2));
+        assert_eq!(data_len(&[1, 2, 3, 0], true), None);
+        assert_eq!(data_len(&[0, 0], true), None);
+        assert_eq!(data_len(&[0, 0], false), Some(0));
+        assert_eq!(data_len(&[], false), Some(0));
+        assert_eq!(data_len(&[0, PADDING_MARKER], false), None);
+    }
+
+    proptest! {
+        #[test]
+        fn padme_overhead_is_bounded(len in 2u64..1 << 60) {
+            let padded = padme(len).expect("Small lengths should not overflow, but");
+            assert!(padded >= len);
+            assert!((padded - len) as f64 <= len as f64 * 0.12);
+            assert_eq!(padme(padded), Some(padded));
+        }
+    }
+}
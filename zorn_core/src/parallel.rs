@@ -0,0 +1,450 @@
+use std::io::{self, BufRead, Read, Write};
+use std::ops::Range;
+use rand_core::{CryptoRng, OsRng, RngCore};
+use rayon::prelude::*;
+use zeroize::Zeroizing;
+
+use crate::chunk::{ChunkError, PayloadCipher};
+use crate::error::Error;
+use crate::header::{Header, Version};
+use crate::identity::{ZornIdentity, ZornIdentitySecret};
+use crate::padding::{self, Padding, PaddingRule};
+use crate::stream::{ChunkOpener, State, StreamError, CHUNK_SIZE, ENCRYPTED_CHUNK_SIZE, TAG_SIZE};
+use crate::uniform::{self, REPRESENTATIVE_LEN};
+
+// 4 MiB of plaintext per batch
+pub const DEFAULT_CHUNKS_IN_FLIGHT: usize = 64;
+
+// Produces the same format as Encryptor, but seals batches of up to
+// chunks_in_flight chunks on the rayon thread pool
+pub struct ParallelEncryptor<W: Write> {
+    writer: W,
+    // Written along with the first batch, as Encryptor does
+    header: Option<Header>,
+    cipher: PayloadCipher,
+    // Plaintext is buffered at the positions of the encrypted chunks, so
+    // that every tag can be appended in place
+    buffer: Zeroizing<Vec<u8>>,
+    chunks_in_flight: usize,
+    len: usize,
+    written: u64,
+    padding: Option<Padding>,
+    // Where the padding starts, once finish() has been called
+    data_len: Option<u64>,
+}
+
+impl<W: Write> ParallelEncryptor<W> {
+    pub fn new(writer: W, sender_secret: &ZornIdentitySecret, recipient_identity: &ZornIdentity, chunks_in_flight: usize) -> Result<Self, Error> {
+        Self::with_rng(writer, OsRng, sender_secret, recipient_identity, chunks_in_flight)
+    }
+
+    pub fn with_rng<T: RngCore + CryptoRng>(writer: W, csprng: T, sender_secret: &ZornIdentitySecret, recipient_identity: &ZornIdentity, chunks_in_flight: usize) -> Result<Self, Error> {
+        let (header, cipher) = PayloadCipher::for_sender(csprng, sender_secret, recipient_identity)?;
+        Ok(Self::with_cipher(writer, Some(header), cipher, chunks_in_flight))
+    }
+
+    // A uniform message, see the uniform module
+    pub fn uniform(writer: W, sender_secret: &ZornIdentitySecret, recipient_identity: &ZornIdentity, chunks_in_flight: usize) -> Result<Self, Error> {
+        Self::uniform_with_rng(writer, OsRng, sender_secret, recipient_identity, chunks_in_flight)
+    }
+
+    pub fn uniform_with_rng<T: RngCore + CryptoRng>(mut writer: W, csprng: T, sender_secret: &ZornIdentitySecret, recipient_identity: &ZornIdentity, chunks_in_flight: usize) -> Result<Self, Error> {
+        let cipher = PayloadCipher::for_sender_uniform(&mut writer, csprng, sender_secret, recipient_identity)?;
+        Ok(Self::with_cipher(writer, None, cipher, chunks_in_flight))
+    }
+
+    fn with_cipher(writer: W, header: Option<Header>, cipher: PayloadCipher, chunks_in_flight: usize) -> Self {
+        let chunks_in_flight = chunks_in_flight.max(1);
+        ParallelEncryptor {
+            writer,
+            header,
+            cipher,
+            buffer: Zeroizing::new(vec![0u8; chunks_in_flight * ENCRYPTED_CHUNK_SIZE]),
+            chunks_in_flight,
+            len: 0,
+            written: 0,
+            padding: None,
+            data_len: None,
+        }
+    }
+
+    // Padded messages are v2, so this has to come before anything is written
+    pub fn with_padding(self, padding: Padding) -> Self {
+        debug_assert_eq!(self.written, 0);
+        let header = self.header.map(|header| Header { version: Version::V2, ..header });
+        ParallelEncryptor { header, padding: Some(padding), ..self }
+    }
+
+    fn write_batch(&mut self, last: bool) -> io::Result<()> {
+        if let Some(header) = self.header.take() {
+            header.write_to(&mut self.writer)?;
+        }
+        let len = std::mem::take(&mut self.len);
+        let chunks = len.div_ceil(CHUNK_SIZE).max(1);
+        let final_len = len - (chunks - 1) * CHUNK_SIZE;
+        let first = self.cipher.reserve_indices(chunks as u64)?;
+        let batch_start = self.written - len as u64;
+        let data_len = self.data_len;
+
+        let cipher = &self.cipher;
+        self.buffer[..chunks * ENCRYPTED_CHUNK_SIZE].par_chunks_mut(ENCRYPTED_CHUNK_SIZE).enumerate().try_for_each(|(i, chunk)| {
+            let is_final = i + 1 == chunks;
+            let chunk_len = if is_final { final_len } else { CHUNK_SIZE };
+            let padded = data_len.is_some_and(|data_len| batch_start + (i * CHUNK_SIZE + chunk_len) as u64 > data_len);
+            let (plaintext, tag) = chunk.split_at_mut(chunk_len);
+            tag[..TAG_SIZE].copy_from_slice(&cipher.seal_at(first + i as u64, plaintext, last && is_final, padded)?);
+            Ok::<_, io::Error>(())
+        })?;
+
+        self.writer.write_all(&self.buffer[..(chunks - 1) * ENCRYPTED_CHUNK_SIZE + final_len + TAG_SIZE])
+    }
+
+    // A full batch is only written once more plaintext arrives, since its
+    // last chunk may need the last-chunk flag
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(padding) = self.padding {
+            let data_len = self.written;
+            let padded_len = padding.padded_len(data_len).ok_or(ChunkError::TooLongForPadding)?;
+            self.data_len = Some(data_len);
+            padding::write_padding(&mut self, padded_len - data_len)?;
+        }
+        self.write_batch(true)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Write for ParallelEncryptor<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.len == self.chunks_in_flight * CHUNK_SIZE {
+            self.write_batch(false)?;
+        }
+
+        let offset = self.len % CHUNK_SIZE;
+        let start = self.len / CHUNK_SIZE * ENCRYPTED_CHUNK_SIZE + offset;
+        let n = buf.len().min(CHUNK_SIZE - offset);
+        if self.data_len.is_none() && self.padding.is_some_and(|padding| padding.padded_len(self.written + n as u64).is_none()) {
+            return Err(ChunkError::TooLongForPadding.into());
+        }
+        self.buffer[start..start + n].copy_from_slice(&buf[..n]);
+        self.len += n;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+// Behaves exactly like Decryptor, but opens batches of up to chunks_in_flight
+// chunks on the rayon thread pool. Plaintext is still only released in order
+// and up to the first chunk that fails to authenticate.
+pub struct ParallelDecryptor<R: Read> {
+    reader: R,
+    opener: ChunkOpener,
+    // A batch of encrypted chunks followed by a single octet of lookahead
+    buffer: Zeroizing<Vec<u8>>,
+    filled: usize,
+    plaintext: Range<usize>,
+    state: State,
+}
+
+impl<R: Read> ParallelDecryptor<R> {
+    pub fn new(reader: R, recipient_secret: &ZornIdentitySecret, sender_identity: &ZornIdentity, chunks_in_flight: usize) -> Result<Self, Error> {
+        Self::with_identities(reader, std::slice::from_ref(recipient_secret), sender_identity, chunks_in_flight)
+    }
+
+    pub fn with_identities(mut reader: R, recipient_secrets: &[ZornIdentitySecret], sender_identity: &ZornIdentity, chunks_in_flight: usize) -> Result<Self, Error> {
+        let header = Header::read_from(&mut reader)?;
+        let opener = ChunkOpener::new(&header, header.encoded_len() as u64, PaddingRule::from(header.version), recipient_secrets, sender_identity)?;
+        Ok(Self::with_opener(reader, opener, chunks_in_flight))
+    }
+
+    // A uniform message, see the uniform module
+    pub fn uniform(reader: R, recipient_secret: &ZornIdentitySecret, sender_identity: &ZornIdentity, chunks_in_flight: usize) -> Result<Self, Error> {
+        Self::uniform_with_identities(reader, std::slice::from_ref(recipient_secret), sender_identity, chunks_in_flight)
+    }
+
+    pub fn uniform_with_identities(mut reader: R, recipient_secrets: &[ZornIdentitySecret], sender_identity: &ZornIdentity, chunks_in_flight: usize) -> Result<Self, Error> {
+        let header = uniform::read_header(&mut reader)?;
+        let opener = ChunkOpener::new(&header, REPRESENTATIVE_LEN as u64, PaddingRule::Optional, recipient_secrets, sender_identity)?;
+        Ok(Self::with_opener(reader, opener, chunks_in_flight))
+    }
+
+    fn with_opener(reader: R, opener: ChunkOpener, chunks_in_flight: usize) -> Self {
+        ParallelDecryptor {
+            reader,
+            opener,
+            buffer: Zeroizing::new(vec![0u8; chunks_in_flight.max(1) * ENCRYPTED_CHUNK_SIZE + 1]),
+            filled: 0,
+            plaintext: 0..0,
+            state: State::Reading,
+        }
+    }
+
+    pub fn authenticated_bytes(&self) -> u64 {
+        self.opener.authenticated_bytes()
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn fill_batch(&mut self) -> io::Result<()> {
+        if self.filled == self.buffer.len() {
+            self.buffer[0] = self.buffer[self.filled - 1];
+            self.filled = 1;
+        }
+
+        while self.filled < self.buffer.len() {
+            match self.reader.read(&mut self.buffer[self.filled..]) {
+                Ok(0) => break,
+                Ok(n) => self.filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn open_batch(&mut self) -> io::Result<()> {
+        self.fill_batch()?;
+
+        let ended = self.filled < self.buffer.len();
+        let len = if ended { self.filled } else { self.filled - 1 };
+        let chunks = len.div_ceil(ENCRYPTED_CHUNK_SIZE);
+        let is_last = |i: usize| ended && i + 1 == chunks;
+
+        // Only the tags are checked in parallel, the chunks are then taken
+        // in order like any other decryptor does
+        let mut opened = vec![None; chunks];
+        let mut pieces: Vec<_> = self.buffer[..len].chunks_mut(ENCRYPTED_CHUNK_SIZE).collect();
+        let mut parallel_start = 0;
+        if !self.opener.key_settled() && chunks > 0 {
+            opened[0] = self.opener.authenticate(pieces[0], is_last(0));
+            parallel_start = 1;
+        }
+        if self.opener.key_settled() {
+            let opener = &self.opener;
+            pieces[parallel_start..].par_iter_mut().zip(opened[parallel_start..].par_iter_mut()).enumerate()
+                .for_each(|(i, (chunk, flags))| {
+                    let i = parallel_start + i;
+                    *flags = opener.authenticate_ahead(i as u64, chunk, is_last(i));
+                });
+        }
+        drop(pieces);
+
+        // Move the authentic plaintext to the front, in order, up to the
+        // first chunk that can't be released. Without any chunk at all the
+        // payload ends right here.
+        let mut released = 0;
+        self.state = State::Failed(StreamError::Truncated { authenticated: self.opener.authenticated_bytes() });
+        for (i, flags) in opened.into_iter().enumerate() {
+            let start = i * ENCRYPTED_CHUNK_SIZE;
+            let end = (start + ENCRYPTED_CHUNK_SIZE).min(len);
+            let (plaintext_len, state) = self.opener.advance(&mut self.buffer[start..end], is_last(i), flags)?;
+            self.buffer.copy_within(start..start + plaintext_len, released);
+            released += plaintext_len;
+            self.state = state;
+            if self.state != State::Reading {
+                break;
+            }
+        }
+
+        self.plaintext = 0..released;
+        Ok(())
+    }
+}
+
+impl<R: Read> BufRead for ParallelDecryptor<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.plaintext.is_empty() {
+            match self.state {
+                State::Reading => self.open_batch()?,
+                State::Finished => break,
+                State::Failed(e) => return Err(e.into()),
+            }
+        }
+        Ok(&self.buffer[self.plaintext.clone()])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.plaintext.start = (self.plaintext.start + amt).min(self.plaintext.end);
+    }
+}
+
+impl<R: Read> Read for ParallelDecryptor<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let available = self.fill_buf()?;
+        let n = buf.len().min(available.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use rand_chacha::ChaCha20Rng;
+    use rand_core::{OsRng, SeedableRng};
+
+    use crate::chunk::PayloadCipher;
+    use crate::header::{Header, Version};
+    use crate::identity::{ZornIdentity, ZornIdentitySecret};
+    use crate::padding::{Padding, PADDING_MARKER};
+    use crate::stream::{Decryptor, Encryptor, StreamError, CHUNK_SIZE, ENCRYPTED_CHUNK_SIZE};
+    use crate::test_util::{encrypt, stream_error};
+
+    use super::{ParallelDecryptor, ParallelEncryptor};
+
+    use proptest::{proptest, prelude::{any, ProptestConfig}, collection::vec};
+
+    fn read_all<R: Read>(mut reader: R) -> (Vec<u8>, Result<(), StreamError>) {
+        let mut plaintext = Vec::new();
+        let result = reader.read_to_end(&mut plaintext).map(|_| ()).map_err(stream_error);
+        (plaintext, result)
+    }
+
+    // The parallel decryptor has to agree with the stream decryptor on
+    // everything, including how much plaintext is released before an error
+    fn assert_decryptors_agree(sender_secret: &ZornIdentitySecret, recipient_secret: &ZornIdentitySecret, file: &[u8]) {
+        let sender_identity = ZornIdentity::from(sender_secret);
+        let expected = read_all(Decryptor::new(file, recipient_secret, &sender_identity).expect("Header should be valid, but"));
+        for chunks_in_flight in [1, 2, 3, 8] {
+            let d = ParallelDecryptor::new(file, recipient_secret, &sender_identity, chunks_in_flight).expect("Header should be valid, but");
+            assert_eq!(read_all(d), expected, "with {} chunks in flight", chunks_in_flight);
+        }
+    }
+
+    #[test]
+    fn parallel_encrypt_chunk_boundaries() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+
+        for len in [0, 1, CHUNK_SIZE, CHUNK_SIZE + 1, 2 * CHUNK_SIZE, 5 * CHUNK_SIZE + 7] {
+            let plaintext: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let mut e = Encryptor::with_rng(Vec::new(), ChaCha20Rng::seed_from_u64(len as u64), &sender_secret, &ZornIdentity::from(&recipient_secret))
+                .expect("Writing to a Vec should succeed, but");
+            e.write_all(&plaintext).expect("Writing to a Vec should succeed, but");
+            let expected = e.finish().expect("Writing to a Vec should succeed, but");
+
+            for chunks_in_flight in [1, 2, 3] {
+                let mut e = ParallelEncryptor::with_rng(Vec::new(), ChaCha20Rng::seed_from_u64(len as u64), &sender_secret, &ZornIdentity::from(&recipient_secret), chunks_in_flight)
+                    .expect("Writing to a Vec should succeed, but");
+                e.write_all(&plaintext).expect("Writing to a Vec should succeed, but");
+                let file = e.finish().expect("Writing to a Vec should succeed, but");
+
+                assert_eq!(file, expected);
+                let d = Decryptor::new(&file[..], &recipient_secret, &ZornIdentity::from(&sender_secret)).expect("Header should be valid, but");
+                assert_eq!(read_all(d), (plaintext.clone(), Ok(())));
+            }
+        }
+    }
+
+    #[test]
+    fn parallel_decrypt_agrees_on_errors() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+        let plaintext: Vec<u8> = (0..4 * CHUNK_SIZE).map(|i| i as u8).collect();
+        let file = encrypt(&sender_secret, &recipient_secret, &plaintext);
+
+        assert_decryptors_agree(&sender_secret, &recipient_secret, &file);
+        for len in [55, 56, 55 + ENCRYPTED_CHUNK_SIZE, 55 + 2 * ENCRYPTED_CHUNK_SIZE + 31, 55 + 3 * ENCRYPTED_CHUNK_SIZE + 100] {
+            assert_decryptors_agree(&sender_secret, &recipient_secret, &file[..len]);
+        }
+        for chunk in 0..4 {
+            let mut modified = file.clone();
+            modified[55 + chunk * ENCRYPTED_CHUNK_SIZE + 7] ^= 1;
+            assert_decryptors_agree(&sender_secret, &recipient_secret, &modified);
+        }
+
+        let mut trailing = encrypt(&sender_secret, &recipient_secret, &plaintext[..2 * CHUNK_SIZE]);
+        trailing.extend_from_slice(&file[55 + 2 * ENCRYPTED_CHUNK_SIZE..]);
+        assert_decryptors_agree(&sender_secret, &recipient_secret, &trailing);
+    }
+
+    #[test]
+    fn parallel_padding_agrees_with_stream() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+
+        for padding in [Padding::Padme, Padding::To(5 * CHUNK_SIZE as u64 + 9)] {
+            for len in [0, CHUNK_SIZE, 2 * CHUNK_SIZE + 3] {
+                let plaintext: Vec<u8> = (0..len).map(|i| i as u8).collect();
+                let mut e = Encryptor::with_rng(Vec::new(), ChaCha20Rng::seed_from_u64(len as u64), &sender_secret, &ZornIdentity::from(&recipient_secret))
+                    .expect("Writing to a Vec should succeed, but")
+                    .with_padding(padding);
+                e.write_all(&plaintext).expect("Writing to a Vec should succeed, but");
+                let expected = e.finish().expect("Writing to a Vec should succeed, but");
+
+                for chunks_in_flight in [1, 2, 3] {
+                    let mut e = ParallelEncryptor::with_rng(Vec::new(), ChaCha20Rng::seed_from_u64(len as u64), &sender_secret, &ZornIdentity::from(&recipient_secret), chunks_in_flight)
+                        .expect("Writing to a Vec should succeed, but")
+                        .with_padding(padding);
+                    e.write_all(&plaintext).expect("Writing to a Vec should succeed, but");
+                    assert_eq!(e.finish().expect("Writing to a Vec should succeed, but"), expected);
+                }
+                let d = ParallelDecryptor::new(&expected[..], &recipient_secret, &ZornIdentity::from(&sender_secret), 2).expect("Header should be valid, but");
+                assert_eq!(read_all(d), (plaintext, Ok(())));
+                assert_decryptors_agree(&sender_secret, &recipient_secret, &expected);
+            }
+        }
+
+        // Whether padding has started only shows once the chunks are released in order
+        let mut marked = vec![0u8; CHUNK_SIZE];
+        marked[..100].fill(0x5a);
+        marked[100] = PADDING_MARKER;
+        let data = vec![0x5au8; CHUNK_SIZE];
+        for chunks in [[(&data, false), (&marked, true), (&data, false)], [(&data, false), (&marked, true), (&data, true)], [(&marked, true), (&marked, true), (&data, false)]] {
+            let (header, mut cipher) = PayloadCipher::for_sender(OsRng, &sender_secret, &ZornIdentity::from(&recipient_secret)).expect("Key exchange should succeed, but");
+            let mut file = Header { version: Version::V2, ..header }.to_bytes().to_vec();
+            for (i, (plaintext, padded)) in chunks.into_iter().enumerate() {
+                let mut chunk = plaintext.clone();
+                let tag = cipher.seal(&mut chunk, i == 2, padded).expect("Chunk should be valid, but");
+                file.extend_from_slice(&chunk);
+                file.extend_from_slice(&tag);
+            }
+            assert_decryptors_agree(&sender_secret, &recipient_secret, &file);
+        }
+    }
+
+    #[test]
+    fn parallel_decrypt_with_identities() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let secrets: Vec<_> = (0..3).map(|_| ZornIdentitySecret::new(OsRng)).collect();
+        let plaintext = vec![0x5au8; 3 * CHUNK_SIZE + 1];
+        let file = encrypt(&sender_secret, &secrets[1], &plaintext);
+
+        let d = ParallelDecryptor::with_identities(&file[..], &secrets, &ZornIdentity::from(&sender_secret), 2).expect("Header should be valid, but");
+        assert_eq!(read_all(d), (plaintext, Ok(())));
+
+        let d = ParallelDecryptor::with_identities(&file[..], &secrets[2..], &ZornIdentity::from(&sender_secret), 2).expect("Header should be valid, but");
+        assert_eq!(read_all(d), (Vec::new(), Err(StreamError::Authentication { chunk: 0, offset: 55 })));
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(16))]
+        #[test]
+        fn parallel_roundtrip(plaintext in vec(any::<u8>(), 0..=3 * CHUNK_SIZE), chunks_in_flight in 1..4usize) {
+            let sender_secret = ZornIdentitySecret::new(OsRng);
+            let recipient_secret = ZornIdentitySecret::new(OsRng);
+
+            let mut e = ParallelEncryptor::new(Vec::new(), &sender_secret, &ZornIdentity::from(&recipient_secret), chunks_in_flight)
+                .expect("Writing to a Vec should succeed, but");
+            e.write_all(&plaintext).expect("Writing to a Vec should succeed, but");
+            let file = e.finish().expect("Writing to a Vec should succeed, but");
+
+            let d = ParallelDecryptor::new(&file[..], &recipient_secret, &ZornIdentity::from(&sender_secret), chunks_in_flight).expect("Header should be valid, but");
+            assert_eq!(read_all(d), (plaintext, Ok(())));
+        }
+    }
+}
@@ -0,0 +1,343 @@
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+use zeroize::Zeroizing;
+
+use crate::chunk::PayloadCipher;
+use crate::error::Error;
+use crate::header::Header;
+use crate::identity::{ZornIdentity, ZornIdentitySecret};
+use crate::padding::{self, PaddingRule};
+use crate::stream::{StreamError, CHUNK_SIZE, ENCRYPTED_CHUNK_SIZE, TAG_SIZE};
+use crate::uniform;
+
+// Random access to the plaintext, authenticating only the chunks that are
+// actually read. The final chunk is authenticated up front, since the
+// plaintext length can't be trusted otherwise. When it is padding, the chunk
+// holding the end of the message is found by bisection, which leaves the
+// padding chunks in between unread like any others.
+pub struct SeekableDecryptor<R: Read + Seek> {
+    reader: R,
+    // One candidate per secret key until the final chunk has been authenticated
+    ciphers: Vec<PayloadCipher>,
+    payload_start: u64,
+    payload_len: u64,
+    last_chunk: u64,
+    padding: PaddingRule,
+    // The first chunk holding any padding, if there is padding
+    padding_start: Option<u64>,
+    plaintext_len: u64,
+    // Plaintext of the most recently authenticated chunk
+    buffer: Zeroizing<Vec<u8>>,
+    chunk: Option<u64>,
+    chunk_len: usize,
+    position: u64,
+}
+
+impl<R: Read + Seek> SeekableDecryptor<R> {
+    pub fn new(reader: R, recipient_secret: &ZornIdentitySecret, sender_identity: &ZornIdentity) -> Result<Self, Error> {
+        Self::with_identities(reader, std::slice::from_ref(recipient_secret), sender_identity)
+    }
+
+    pub fn with_identities(mut reader: R, recipient_secrets: &[ZornIdentitySecret], sender_identity: &ZornIdentity) -> Result<Self, Error> {
+        let header = Header::read_from(&mut reader)?;
+        Self::with_header(reader, &header, PaddingRule::from(header.version), recipient_secrets, sender_identity)
+    }
+
+    // A uniform message, see the uniform module
+    pub fn uniform(reader: R, recipient_secret: &ZornIdentitySecret, sender_identity: &ZornIdentity) -> Result<Self, Error> {
+        Self::uniform_with_identities(reader, std::slice::from_ref(recipient_secret), sender_identity)
+    }
+
+    pub fn uniform_with_identities(mut reader: R, recipient_secrets: &[ZornIdentitySecret], sender_identity: &ZornIdentity) -> Result<Self, Error> {
+        let header = uniform::read_header(&mut reader)?;
+        Self::with_header(reader, &header, PaddingRule::Optional, recipient_secrets, sender_identity)
+    }
+
+    // The payload starts wherever the reader is left after the header
+    fn with_header(mut reader: R, header: &Header, padding: PaddingRule, recipient_secrets: &[ZornIdentitySecret], sender_identity: &ZornIdentity) -> Result<Self, Error> {
+        let ciphers = PayloadCipher::for_recipients(header, recipient_secrets, sender_identity)?;
+
+        let payload_start = reader.stream_position()?;
+        let payload_len = reader.seek(SeekFrom::End(0))?.saturating_sub(payload_start);
+        let last_chunk = payload_len.div_ceil(ENCRYPTED_CHUNK_SIZE as u64).max(1) - 1;
+
+        let mut decryptor = SeekableDecryptor {
+            reader,
+            ciphers,
+            payload_start,
+            payload_len,
+            last_chunk,
+            padding,
+            padding_start: None,
+            plaintext_len: 0,
+            buffer: Zeroizing::new(vec![0u8; ENCRYPTED_CHUNK_SIZE]),
+            chunk: None,
+            chunk_len: 0,
+            position: 0,
+        };
+        let end_chunk = match decryptor.open_chunk(last_chunk)? {
+            true => {
+                let padding_start = decryptor.find_padding_start()?;
+                decryptor.padding_start = Some(padding_start);
+                padding_start
+            }
+            false if padding == PaddingRule::Required => return Err(StreamError::Padding { chunk: last_chunk }.into()),
+            false => last_chunk,
+        };
+        decryptor.load_chunk(end_chunk)?;
+        decryptor.plaintext_len = end_chunk * CHUNK_SIZE as u64 + decryptor.chunk_len as u64;
+        Ok(decryptor)
+    }
+
+    pub fn plaintext_len(&self) -> u64 {
+        self.plaintext_len
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn load_chunk(&mut self, n: u64) -> io::Result<()> {
+        if self.chunk == Some(n) {
+            return Ok(());
+        }
+        self.chunk = None;
+
+        // Everything from the first padded chunk on has to be padding
+        let padded = self.open_chunk(n)?;
+        if padded != self.padding_start.is_some_and(|padding_start| n >= padding_start) {
+            return Err(StreamError::Authentication { chunk: n, offset: self.chunk_offset(n) }.into());
+        }
+        if self.padding_start == Some(n) {
+            self.chunk_len = padding::data_len(&self.buffer[..self.chunk_len], true).ok_or(StreamError::Padding { chunk: n })?;
+        }
+        self.chunk = Some(n);
+        Ok(())
+    }
+
+    fn chunk_offset(&self, n: u64) -> u64 {
+        self.payload_start + n * ENCRYPTED_CHUNK_SIZE as u64
+    }
+
+    // Authenticates and decrypts chunk n into the buffer, returning whether it is padding
+    fn open_chunk(&mut self, n: u64) -> io::Result<bool> {
+        // Nothing has been released when the final chunk turns out to be
+        // missing, so no plaintext counts as authenticated
+        let start = n * ENCRYPTED_CHUNK_SIZE as u64;
+        let len = (self.payload_len - start).min(ENCRYPTED_CHUNK_SIZE as u64) as usize;
+        if len < TAG_SIZE {
+            return Err(StreamError::Truncated { authenticated: 0 }.into());
+        }
+        self.reader.seek(SeekFrom::Start(self.payload_start + start))?;
+        self.reader.read_exact(&mut self.buffer[..len])?;
+
+        let last = n == self.last_chunk;
+        let (ciphertext, tag) = self.buffer[..len].split_at_mut(len - TAG_SIZE);
+        let opened = self.ciphers.iter().enumerate().find_map(|(i, cipher)| Some((i, cipher.open_any(n, ciphertext, tag, last, false, self.padding != PaddingRule::Forbidden)?)));
+        let (i, flags) = match opened {
+            Some((i, flags)) if flags.last == last => (i, flags),
+            Some(_) if last => return Err(StreamError::Truncated { authenticated: 0 }.into()),
+            _ => return Err(StreamError::Authentication { chunk: n, offset: self.chunk_offset(n) }.into()),
+        };
+        self.ciphers.swap(0, i);
+        self.ciphers.truncate(1);
+
+        self.chunk_len = len - TAG_SIZE;
+        Ok(flags.padded)
+    }
+
+    // Padding chunks are all at the end, so the first one can be bisected for
+    fn find_padding_start(&mut self) -> io::Result<u64> {
+        let (mut low, mut high) = (0, self.last_chunk);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            if self.open_chunk(middle)? {
+                high = middle;
+            } else {
+                low = middle + 1;
+            }
+        }
+        Ok(high)
+    }
+}
+
+impl<R: Read + Seek> BufRead for SeekableDecryptor<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.position >= self.plaintext_len {
+            return Ok(&[]);
+        }
+
+        self.load_chunk(self.position / CHUNK_SIZE as u64)?;
+        let start = (self.position % CHUNK_SIZE as u64) as usize;
+        Ok(&self.buffer[start..self.chunk_len])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.position += amt as u64;
+    }
+}
+
+impl<R: Read + Seek> Read for SeekableDecryptor<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = buf.len().min(available.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek> Seek for SeekableDecryptor<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(position) => (position, 0),
+            SeekFrom::End(offset) => (self.plaintext_len, offset),
+            SeekFrom::Current(offset) => (self.position, offset),
+        };
+        self.position = base.checked_add_signed(offset)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative or overflowing position"))?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::assert_matches::assert_matches;
+    use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+    use rand_core::OsRng;
+
+    use crate::error::Error;
+    use crate::identity::{ZornIdentity, ZornIdentitySecret};
+    use crate::padding::Padding;
+    use crate::stream::{Encryptor, CHUNK_SIZE, ENCRYPTED_CHUNK_SIZE, TAG_SIZE};
+    use crate::test_util::encrypt;
+
+    use super::SeekableDecryptor;
+
+    use proptest::{proptest, prelude::ProptestConfig};
+
+    fn open(sender_secret: &ZornIdentitySecret, recipient_secret: &ZornIdentitySecret, file: Vec<u8>) -> Result<SeekableDecryptor<Cursor<Vec<u8>>>, Error> {
+        SeekableDecryptor::new(Cursor::new(file), recipient_secret, &ZornIdentity::from(sender_secret))
+    }
+
+    fn read_range<R: Read + Seek>(d: &mut R, offset: u64, len: u64) -> Result<Vec<u8>, Error> {
+        d.seek(SeekFrom::Start(offset))?;
+        let mut buf = Vec::new();
+        d.by_ref().take(len).read_to_end(&mut buf)?;
+        Ok(buf)
+    }
+
+    #[test]
+    fn seekable_plaintext_len() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+
+        for len in [0, 1, CHUNK_SIZE, CHUNK_SIZE + 1, 3 * CHUNK_SIZE] {
+            let d = open(&sender_secret, &recipient_secret, encrypt(&sender_secret, &recipient_secret, &vec![0x5au8; len]))
+                .expect("File should be valid, but");
+            assert_eq!(d.plaintext_len(), len as u64);
+        }
+    }
+
+    #[test]
+    fn seekable_reads_only_covering_chunks() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+        let plaintext: Vec<u8> = (0..3 * CHUNK_SIZE + 100).map(|i| (i % 251) as u8).collect();
+        let mut file = encrypt(&sender_secret, &recipient_secret, &plaintext);
+        file[55 + 10] ^= 1;
+
+        let mut d = open(&sender_secret, &recipient_secret, file).expect("File should be valid, but");
+        let range = CHUNK_SIZE as u64 + 10..3 * CHUNK_SIZE as u64 + 10;
+        assert_eq!(read_range(&mut d, range.start, range.end - range.start).expect("Decryption should succeed, but"), &plaintext[range.start as usize..range.end as usize]);
+        assert_eq!(read_range(&mut d, 3 * CHUNK_SIZE as u64, 1000).expect("Decryption should succeed, but"), &plaintext[3 * CHUNK_SIZE..]);
+        assert_eq!(read_range(&mut d, plaintext.len() as u64 + 5, 10).expect("Reading past the end should succeed, but"), b"");
+        assert_matches!(read_range(&mut d, 5, 10), Err(Error::Authentication { chunk: 0, offset: 55 }));
+
+        d.seek(SeekFrom::End(-1)).expect("Seeking should succeed, but");
+        assert_eq!(d.stream_position().expect("Seeking should succeed, but"), plaintext.len() as u64 - 1);
+        assert!(d.seek(SeekFrom::Current(-(plaintext.len() as i64))).is_err());
+    }
+
+    #[test]
+    fn seekable_requires_final_chunk() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+        let file = encrypt(&sender_secret, &recipient_secret, &vec![0x5au8; 2 * CHUNK_SIZE + 1]);
+
+        assert_matches!(open(&sender_secret, &recipient_secret, file[..55 + 2 * ENCRYPTED_CHUNK_SIZE].to_vec()).map(|d| d.plaintext_len()),
+            Err(Error::Truncated { authenticated: 0 }));
+        assert_matches!(open(&sender_secret, &recipient_secret, file[..55 + 2 * ENCRYPTED_CHUNK_SIZE + TAG_SIZE - 1].to_vec()).map(|d| d.plaintext_len()),
+            Err(Error::Truncated { authenticated: 0 }));
+        assert_matches!(open(&sender_secret, &recipient_secret, file[..55].to_vec()).map(|d| d.plaintext_len()),
+            Err(Error::Truncated { authenticated: 0 }));
+        assert_matches!(open(&sender_secret, &recipient_secret, file[..file.len() - 1].to_vec()).map(|d| d.plaintext_len()),
+            Err(Error::Authentication { chunk: 2, .. }));
+        assert_matches!(open(&ZornIdentitySecret::new(OsRng), &recipient_secret, file).map(|d| d.plaintext_len()),
+            Err(Error::Authentication { chunk: 2, .. }));
+    }
+
+    #[test]
+    fn seekable_strips_padding() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+
+        for padding in [Padding::Padme, Padding::To(6 * CHUNK_SIZE as u64 + 1)] {
+            for len in [0, 100, CHUNK_SIZE - 1, CHUNK_SIZE, 2 * CHUNK_SIZE + 5] {
+                let plaintext: Vec<u8> = (0..len).map(|i| i as u8).collect();
+                let mut e = Encryptor::new(Vec::new(), &sender_secret, &ZornIdentity::from(&recipient_secret)).expect("Writing to a Vec should succeed, but")
+                    .with_padding(padding);
+                e.write_all(&plaintext).expect("Writing to a Vec should succeed, but");
+                let file = e.finish().expect("Writing to a Vec should succeed, but");
+
+                let mut d = open(&sender_secret, &recipient_secret, file).expect("File should be valid, but");
+                assert_eq!(d.plaintext_len(), len as u64);
+                assert_eq!(read_range(&mut d, len.saturating_sub(10) as u64, u64::MAX).expect("Decryption should succeed, but"), &plaintext[len.saturating_sub(10)..]);
+                assert_eq!(read_range(&mut d, 0, u64::MAX).expect("Decryption should succeed, but"), plaintext);
+            }
+        }
+    }
+
+    // The version isn't bound to the key, so a message can be relabelled
+    #[test]
+    fn seekable_checks_padding_against_the_version() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+        let mut unpadded = encrypt(&sender_secret, &recipient_secret, &vec![0x5au8; CHUNK_SIZE + 1]);
+        let mut e = Encryptor::new(Vec::new(), &sender_secret, &ZornIdentity::from(&recipient_secret)).expect("Writing to a Vec should succeed, but")
+            .with_padding(Padding::To(2 * CHUNK_SIZE as u64));
+        e.write_all(&[0x5a; CHUNK_SIZE + 1]).expect("Writing to a Vec should succeed, but");
+        let mut padded = e.finish().expect("Writing to a Vec should succeed, but");
+
+        unpadded[21] = b'2';
+        padded[21] = b'1';
+        assert_matches!(open(&sender_secret, &recipient_secret, unpadded).map(|d| d.plaintext_len()), Err(Error::Padding { chunk: 1 }));
+        assert_matches!(open(&sender_secret, &recipient_secret, padded).map(|d| d.plaintext_len()), Err(Error::Authentication { chunk: 1, .. }));
+    }
+
+    #[test]
+    fn seekable_with_identities() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let secrets = [ZornIdentitySecret::new(OsRng), ZornIdentitySecret::new(OsRng)];
+        let plaintext = vec![0x5au8; CHUNK_SIZE + 1];
+        let file = encrypt(&sender_secret, &secrets[1], &plaintext);
+
+        let mut d = SeekableDecryptor::with_identities(Cursor::new(file), &secrets, &ZornIdentity::from(&sender_secret))
+            .expect("File should be valid, but");
+        assert_eq!(read_range(&mut d, 0, u64::MAX).expect("Decryption should succeed, but"), plaintext);
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+        #[test]
+        fn seekable_arbitrary_ranges(len in 0..3 * CHUNK_SIZE, offset in 0..4 * CHUNK_SIZE as u64, range_len in 0..2 * CHUNK_SIZE as u64) {
+            let sender_secret = ZornIdentitySecret::new(OsRng);
+            let recipient_secret = ZornIdentitySecret::new(OsRng);
+            let plaintext: Vec<u8> = (0..len).map(|i| i as u8).collect();
+
+            let mut d = open(&sender_secret, &recipient_secret, encrypt(&sender_secret, &recipient_secret, &plaintext)).expect("File should be valid, but");
+            let start = (offset as usize).min(len);
+            let end = (offset + range_len).min(len as u64) as usize;
+            assert_eq!(read_range(&mut d, offset, range_len).expect("Decryption should succeed, but"), &plaintext[start..end.max(start)]);
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use aead::{AeadInPlace, KeyInit, Tag, generic_array::GenericArray};
+use rand_core::{CryptoRng, RngCore};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use crate::chunk::{chunk_nonce, CHUNK_SIZE, ENCRYPTED_CHUNK_SIZE, TAG_SIZE};
+use crate::error::Error;
+use crate::xchacha20_blake3::XChaCha20Blake3;
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("spooled record {0} was modified")]
+pub struct SpoolError(u64);
+
+impl From<SpoolError> for io::Error {
+    fn from(e: SpoolError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+// Holds plaintext back until a whole message has been authenticated. It is
+// sealed in records of CHUNK_SIZE under a random key that never leaves memory,
+// so the backing file only ever sees ciphertext and a reader only ever needs a
+// single record in memory.
+pub struct Spool<F: Read + Write + Seek> {
+    file: F,
+    aead: XChaCha20Blake3,
+    buffer: Zeroizing<Vec<u8>>,
+    len: usize,
+    records: u64,
+}
+
+impl<F: Read + Write + Seek> Spool<F> {
+    pub fn new<T: RngCore + CryptoRng>(file: F, mut csprng: T) -> Result<Self, Error> {
+        let mut key = Zeroizing::new([0u8; 32]);
+        csprng.try_fill_bytes(&mut key[..])?;
+        Ok(Spool {
+            file,
+            aead: XChaCha20Blake3::new(GenericArray::from_slice(&key[..])),
+            buffer: Zeroizing::new(vec![0u8; ENCRYPTED_CHUNK_SIZE]),
+            len: 0,
+            records: 0,
+        })
+    }
+
+    fn write_record(&mut self, last: bool) -> io::Result<()> {
+        let len = std::mem::take(&mut self.len);
+        let (plaintext, tag) = self.buffer.split_at_mut(len);
+        let sealed = self.aead.encrypt_in_place_detached(&chunk_nonce(self.records, last, false), &[], plaintext)
+            .map_err(|_| io::Error::other("spool record is too long"))?;
+        tag[..TAG_SIZE].copy_from_slice(&sealed);
+        self.records += 1;
+        self.file.write_all(&self.buffer[..len + TAG_SIZE])
+    }
+
+    // Like the Encryptor, a full record is only written once more plaintext
+    // arrives, so that the last record carries the last-record flag
+    pub fn finish(mut self) -> io::Result<SpoolReader<F>> {
+        let last_len = self.len;
+        self.write_record(true)?;
+        self.file.flush()?;
+        self.file.seek(SeekFrom::Start(0))?;
+        Ok(SpoolReader {
+            file: self.file,
+            aead: self.aead,
+            buffer: self.buffer,
+            plaintext: 0..0,
+            records: self.records,
+            last_len,
+            index: 0,
+        })
+    }
+}
+
+impl<F: Read + Write + Seek> Write for Spool<F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.len == CHUNK_SIZE {
+            self.write_record(false)?;
+        }
+
+        let n = buf.len().min(CHUNK_SIZE - self.len);
+        self.buffer[self.len..self.len + n].copy_from_slice(&buf[..n]);
+        self.len += n;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+pub struct SpoolReader<F: Read> {
+    file: F,
+    aead: XChaCha20Blake3,
+    buffer: Zeroizing<Vec<u8>>,
+    plaintext: Range<usize>,
+    records: u64,
+    last_len: usize,
+    index: u64,
+}
+
+impl<F: Read> SpoolReader<F> {
+    fn read_record(&mut self) -> io::Result<()> {
+        let n = self.index;
+        let last = n + 1 == self.records;
+        let len = if last { self.last_len } else { CHUNK_SIZE };
+        self.file.read_exact(&mut self.buffer[..len + TAG_SIZE])?;
+
+        let (ciphertext, tag) = self.buffer[..len + TAG_SIZE].split_at_mut(len);
+        self.aead.decrypt_in_place_detached(&chunk_nonce(n, last, false), &[], ciphertext, Tag::<XChaCha20Blake3>::from_slice(tag))
+            .map_err(|_| SpoolError(n))?;
+        self.index += 1;
+        self.plaintext = 0..len;
+        Ok(())
+    }
+}
+
+impl<F: Read> Read for SpoolReader<F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.plaintext.is_empty() && self.index < self.records {
+            self.read_record()?;
+        }
+
+        let n = buf.len().min(self.plaintext.len());
+        buf[..n].copy_from_slice(&self.buffer[self.plaintext.start..self.plaintext.start + n]);
+        self.plaintext.start += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read, Write};
+    use rand_core::OsRng;
+
+    use crate::chunk::{CHUNK_SIZE, ENCRYPTED_CHUNK_SIZE};
+
+    use super::{Spool, SpoolError};
+
+    fn spool(plaintext: &[u8]) -> Vec<u8> {
+        let mut s = Spool::new(Cursor::new(Vec::new()), OsRng).expect("OsRng should not fail, but");
+        s.write_all(plaintext).expect("Writing to a Vec should succeed, but");
+        s.finish().expect("Writing to a Vec should succeed, but").file.into_inner()
+    }
+
+    #[test]
+    fn spool_roundtrip() {
+        for len in [0, 1, CHUNK_SIZE, 2 * CHUNK_SIZE + 7] {
+            let plaintext: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let mut s = Spool::new(Cursor::new(Vec::new()), OsRng).expect("OsRng should not fail, but");
+            s.write_all(&plaintext).expect("Writing to a Vec should succeed, but");
+            let mut reader = s.finish().expect("Writing to a Vec should succeed, but");
+
+            let mut read_back = Vec::new();
+            reader.read_to_end(&mut read_back).expect("Spool should be intact, but");
+            assert_eq!(read_back, plaintext);
+        }
+    }
+
+    #[test]
+    fn spool_only_holds_ciphertext() {
+        let plaintext = vec![0x5au8; 2 * CHUNK_SIZE];
+        let file = spool(&plaintext);
+        assert_eq!(file.len(), 2 * ENCRYPTED_CHUNK_SIZE);
+        assert!(!file.windows(64).any(|w| w.iter().all(|&b| b == 0x5a)));
+    }
+
+    #[test]
+    fn spool_detects_modification() {
+        let plaintext = vec![0x5au8; 2 * CHUNK_SIZE];
+        let mut s = Spool::new(Cursor::new(Vec::new()), OsRng).expect("OsRng should not fail, but");
+        s.write_all(&plaintext).expect("Writing to a Vec should succeed, but");
+        let mut reader = s.finish().expect("Writing to a Vec should succeed, but");
+        reader.file.get_mut()[ENCRYPTED_CHUNK_SIZE + 3] ^= 1;
+
+        let e = reader.read_to_end(&mut Vec::new()).expect_err("Modified spool should fail, but");
+        assert_eq!(e.get_ref().and_then(|e| e.downcast_ref::<SpoolError>()), Some(&SpoolError(1)));
+    }
+}
@@ -0,0 +1,850 @@
+use std::io::{self, BufRead, Read, Write};
+use std::ops::Range;
+use rand_core::{CryptoRng, OsRng, RngCore};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use crate::chunk::{ChunkError, ChunkFlags, PayloadCipher};
+use crate::error::Error;
+use crate::header::{Header, Version};
+use crate::identity::{ZornIdentity, ZornIdentitySecret};
+use crate::key_exchange::SharedSecret;
+use crate::padding::{self, Padding, PaddingRule};
+use crate::uniform::{self, REPRESENTATIVE_LEN};
+
+pub use crate::chunk::{CHUNK_SIZE, TAG_SIZE, ENCRYPTED_CHUNK_SIZE};
+
+// Length of the payload following the header for a plaintext of the given length
+pub fn ciphertext_len(plaintext_len: u64) -> u64 {
+    let chunks = plaintext_len.div_ceil(CHUNK_SIZE as u64).max(1);
+    plaintext_len + chunks * TAG_SIZE as u64
+}
+
+// The inverse of ciphertext_len, for payload lengths an encryptor can produce
+pub fn plaintext_len(ciphertext_len: u64) -> Option<u64> {
+    let full_chunks = ciphertext_len / ENCRYPTED_CHUNK_SIZE as u64;
+    match ciphertext_len % ENCRYPTED_CHUNK_SIZE as u64 {
+        0 if full_chunks > 0 => Some(full_chunks * CHUNK_SIZE as u64),
+        rest if rest == TAG_SIZE as u64 && full_chunks > 0 => None,
+        rest if rest >= TAG_SIZE as u64 => Some(full_chunks * CHUNK_SIZE as u64 + rest - TAG_SIZE as u64),
+        _ => None,
+    }
+}
+
+// Offset into the payload of the ciphertext octet encrypting the plaintext
+// octet at plaintext_offset
+pub fn ciphertext_offset(plaintext_offset: u64) -> u64 {
+    let chunk = plaintext_offset / CHUNK_SIZE as u64;
+    chunk * ENCRYPTED_CHUNK_SIZE as u64 + plaintext_offset % CHUNK_SIZE as u64
+}
+
+impl PayloadCipher {
+    // Picks a fresh ephemeral identity, and leaves writing its header to the
+    // caller since padding changes the version
+    pub(crate) fn for_sender<T: RngCore + CryptoRng>(csprng: T, sender_secret: &ZornIdentitySecret, recipient_identity: &ZornIdentity) -> Result<(Header, Self), Error> {
+        let (ephemeral_identity, shared_secret) = SharedSecret::sender_with_rng(csprng, sender_secret, recipient_identity)?;
+        let cipher = PayloadCipher::new(&shared_secret, &ephemeral_identity, &ZornIdentity::from(sender_secret), recipient_identity);
+        Ok((Header::new(ephemeral_identity), cipher))
+    }
+
+    // Writes the representative of a fresh ephemeral identity in place of the header
+    pub(crate) fn for_sender_uniform<W: Write, T: RngCore + CryptoRng>(writer: &mut W, csprng: T, sender_secret: &ZornIdentitySecret, recipient_identity: &ZornIdentity) -> Result<Self, Error> {
+        let (representative, ephemeral_identity, shared_secret) = SharedSecret::sender_uniform_with_rng(csprng, sender_secret, recipient_identity)?;
+        writer.write_all(&representative)?;
+        Ok(PayloadCipher::new(&shared_secret, &ephemeral_identity, &ZornIdentity::from(sender_secret), recipient_identity))
+    }
+
+    pub(crate) fn for_recipients(header: &Header, recipient_secrets: &[ZornIdentitySecret], sender_identity: &ZornIdentity) -> Result<Vec<Self>, Error> {
+        recipient_secrets.iter().map(|recipient_secret| {
+            let shared_secret = SharedSecret::recipient(recipient_secret, sender_identity, &header.ephemeral_identity)?;
+            Ok(PayloadCipher::new(&shared_secret, &header.ephemeral_identity, sender_identity, &ZornIdentity::from(recipient_secret)))
+        }).collect()
+    }
+}
+
+// Dropping an Encryptor without calling finish() produces a truncated payload
+pub struct Encryptor<W: Write> {
+    writer: W,
+    // Written along with the first chunk, which leaves with_padding() time
+    // to change the version. Uniform messages have none.
+    header: Option<Header>,
+    cipher: PayloadCipher,
+    buffer: Zeroizing<Vec<u8>>,
+    len: usize,
+    written: u64,
+    padding: Option<Padding>,
+    // Where the padding starts, once finish() has been called
+    data_len: Option<u64>,
+}
+
+impl<W: Write> Encryptor<W> {
+    pub fn new(writer: W, sender_secret: &ZornIdentitySecret, recipient_identity: &ZornIdentity) -> Result<Self, Error> {
+        Self::with_rng(writer, OsRng, sender_secret, recipient_identity)
+    }
+
+    // The ephemeral key is the only randomness in a zorn file, so a seeded
+    // csprng makes the output reproducible
+    pub fn with_rng<T: RngCore + CryptoRng>(writer: W, csprng: T, sender_secret: &ZornIdentitySecret, recipient_identity: &ZornIdentity) -> Result<Self, Error> {
+        let (header, cipher) = PayloadCipher::for_sender(csprng, sender_secret, recipient_identity)?;
+        Ok(Self::with_cipher(writer, Some(header), cipher))
+    }
+
+    // A uniform message, see the uniform module
+    pub fn uniform(writer: W, sender_secret: &ZornIdentitySecret, recipient_identity: &ZornIdentity) -> Result<Self, Error> {
+        Self::uniform_with_rng(writer, OsRng, sender_secret, recipient_identity)
+    }
+
+    pub fn uniform_with_rng<T: RngCore + CryptoRng>(mut writer: W, csprng: T, sender_secret: &ZornIdentitySecret, recipient_identity: &ZornIdentity) -> Result<Self, Error> {
+        let cipher = PayloadCipher::for_sender_uniform(&mut writer, csprng, sender_secret, recipient_identity)?;
+        Ok(Self::with_cipher(writer, None, cipher))
+    }
+
+    fn with_cipher(writer: W, header: Option<Header>, cipher: PayloadCipher) -> Self {
+        Encryptor {
+            writer,
+            header,
+            cipher,
+            buffer: Zeroizing::new(vec![0u8; ENCRYPTED_CHUNK_SIZE]),
+            len: 0,
+            written: 0,
+            padding: None,
+            data_len: None,
+        }
+    }
+
+    // Padded messages are v2, so this has to come before anything is written
+    pub fn with_padding(self, padding: Padding) -> Self {
+        debug_assert_eq!(self.written, 0);
+        let header = self.header.map(|header| Header { version: Version::V2, ..header });
+        Encryptor { header, padding: Some(padding), ..self }
+    }
+
+    // The buffered chunk ends at the last octet written so far
+    fn write_chunk(&mut self, last: bool) -> io::Result<()> {
+        if let Some(header) = self.header.take() {
+            header.write_to(&mut self.writer)?;
+        }
+        let len = std::mem::take(&mut self.len);
+        let padded = self.data_len.is_some_and(|data_len| self.written > data_len);
+        let (plaintext, tag) = self.buffer.split_at_mut(len);
+        tag[..TAG_SIZE].copy_from_slice(&self.cipher.seal(plaintext, last, padded)?);
+        self.writer.write_all(&self.buffer[..len + TAG_SIZE])
+    }
+
+    // A full chunk is only written once more plaintext arrives, since the
+    // last chunk may itself be full and needs the last-chunk flag
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(padding) = self.padding {
+            let data_len = self.written;
+            let padded_len = padding.padded_len(data_len).ok_or(ChunkError::TooLongForPadding)?;
+            self.data_len = Some(data_len);
+            padding::write_padding(&mut self, padded_len - data_len)?;
+        }
+        self.write_chunk(true)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Write for Encryptor<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.len == CHUNK_SIZE {
+            self.write_chunk(false)?;
+        }
+
+        let n = buf.len().min(CHUNK_SIZE - self.len);
+        // Fail early rather than after encrypting a message that can't be padded
+        if self.data_len.is_none() && self.padding.is_some_and(|padding| padding.padded_len(self.written + n as u64).is_none()) {
+            return Err(ChunkError::TooLongForPadding.into());
+        }
+        self.buffer[self.len..self.len + n].copy_from_slice(&buf[..n]);
+        self.len += n;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamError {
+    #[error("authentication failed for chunk {chunk} at offset {offset}")]
+    Authentication { chunk: u64, offset: u64 },
+    #[error("payload is truncated after {authenticated} authenticated bytes")]
+    Truncated { authenticated: u64 },
+    #[error("data follows the final chunk")]
+    TrailingData,
+    #[error("padding in chunk {chunk} is malformed")]
+    Padding { chunk: u64 },
+}
+
+impl From<StreamError> for io::Error {
+    fn from(e: StreamError) -> Self {
+        match e {
+            StreamError::Truncated { .. } => io::Error::new(io::ErrorKind::UnexpectedEof, e),
+            _ => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum State {
+    Reading,
+    Finished,
+    Failed(StreamError),
+}
+
+// Opens encrypted chunks in order, wherever they are read from
+pub(crate) struct ChunkOpener {
+    // One candidate per secret key until the first chunk has been authenticated
+    ciphers: Vec<PayloadCipher>,
+    payload_start: u64,
+    index: u64,
+    authenticated: u64,
+    // Whether padding has started, after which there is no more plaintext
+    padded: bool,
+    padding: PaddingRule,
+    // Only check the tags and leave the ciphertext as it is
+    verify_only: bool,
+}
+
+impl ChunkOpener {
+    // payload_start is where the first chunk is in the message, for errors
+    pub(crate) fn new(header: &Header, payload_start: u64, padding: PaddingRule, recipient_secrets: &[ZornIdentitySecret], sender_identity: &ZornIdentity) -> Result<Self, Error> {
+        Ok(ChunkOpener {
+            ciphers: PayloadCipher::for_recipients(header, recipient_secrets, sender_identity)?,
+            payload_start,
+            index: 0,
+            authenticated: 0,
+            padded: false,
+            padding,
+            verify_only: false,
+        })
+    }
+
+    pub(crate) fn verify_only(self) -> Self {
+        ChunkOpener { verify_only: true, ..self }
+    }
+
+    pub(crate) fn authenticated_bytes(&self) -> u64 {
+        self.authenticated
+    }
+
+    // Decrypts chunk in place, where last tells whether the payload ends
+    // after it. Returns how much of the chunk is plaintext that may be
+    // released, and the state of the stream afterwards.
+    pub(crate) fn open(&mut self, chunk: &mut [u8], last: bool) -> io::Result<(usize, State)> {
+        let flags = self.authenticate(chunk, last);
+        self.advance(chunk, last, flags)
+    }
+
+    // Checks the tag of the next chunk with every candidate key in turn and
+    // settles on the first one that fits
+    pub(crate) fn authenticate(&mut self, chunk: &mut [u8], last: bool) -> Option<ChunkFlags> {
+        let (n, padded, may_pad, verify_only) = (self.index, self.padded, self.padding != PaddingRule::Forbidden, self.verify_only);
+        let (i, flags) = self.ciphers.iter().enumerate()
+            .find_map(|(i, cipher)| Some((i, authenticate(cipher, n, chunk, last, padded, may_pad, verify_only)?)))?;
+        self.ciphers.swap(0, i);
+        self.ciphers.truncate(1);
+        Some(flags)
+    }
+
+    #[cfg(feature = "parallel")]
+    pub(crate) fn key_settled(&self) -> bool {
+        self.ciphers.len() == 1
+    }
+
+    // Checks the tag of the chunk ahead chunks after the next one, before
+    // the ones in between have been opened, so that it can run on any thread
+    #[cfg(feature = "parallel")]
+    pub(crate) fn authenticate_ahead(&self, ahead: u64, chunk: &mut [u8], last: bool) -> Option<ChunkFlags> {
+        debug_assert!(self.key_settled());
+        authenticate(&self.ciphers[0], self.index.saturating_add(ahead), chunk, last, self.padded, self.padding != PaddingRule::Forbidden, self.verify_only)
+    }
+
+    // Takes the next chunk in order once its tag has been checked, with flags
+    // None if that failed. Returns the same as open().
+    pub(crate) fn advance(&mut self, chunk: &mut [u8], last: bool, flags: Option<ChunkFlags>) -> io::Result<(usize, State)> {
+        if chunk.len() < TAG_SIZE {
+            return Ok((0, State::Failed(StreamError::Truncated { authenticated: self.authenticated })));
+        }
+
+        let n = self.index;
+        let failed = State::Failed(StreamError::Authentication { chunk: n, offset: self.payload_start + n * ENCRYPTED_CHUNK_SIZE as u64 });
+        let Some(flags) = flags else {
+            return Ok((0, failed));
+        };
+        // Only padding may follow padding, which a chunk checked ahead can't know yet
+        if self.padded && !flags.padded {
+            return Ok((0, failed));
+        }
+
+        let (ciphertext, tag) = chunk.split_at_mut(chunk.len() - TAG_SIZE);
+        // Even a verifier has to decrypt padding to tell where the message ends
+        let len = if flags.padded {
+            if self.verify_only {
+                self.ciphers[0].open(n, ciphertext, tag, flags.last, true).expect("The chunk has just been verified");
+            }
+            let Some(len) = padding::data_len(ciphertext, !self.padded) else {
+                return Ok((0, State::Failed(StreamError::Padding { chunk: n })));
+            };
+            self.padded = true;
+            len
+        } else {
+            ciphertext.len()
+        };
+
+        // A padded message that ends without padding has lost it
+        if flags.last && last && self.padding == PaddingRule::Required && !self.padded {
+            return Ok((0, State::Failed(StreamError::Padding { chunk: n })));
+        }
+
+        let state = match (flags.last == last, last) {
+            (true, true) => State::Finished,
+            (true, false) => State::Reading,
+            // An authentic chunk with the wrong flag for its position either
+            // ends the stream early or is followed by data it should not be
+            (false, true) => State::Failed(StreamError::Truncated { authenticated: self.authenticated + len as u64 }),
+            (false, false) => return Ok((0, State::Failed(StreamError::TrailingData))),
+        };
+
+        self.index = n.checked_add(1).ok_or(ChunkError::TooManyChunks)?;
+        self.authenticated += len as u64;
+        Ok((len, state))
+    }
+}
+
+// The tag check of chunk n, which also decrypts it unless only verifying
+fn authenticate(cipher: &PayloadCipher, n: u64, chunk: &mut [u8], last: bool, padded: bool, may_pad: bool, verify_only: bool) -> Option<ChunkFlags> {
+    if chunk.len() < TAG_SIZE {
+        return None;
+    }
+    let (ciphertext, tag) = chunk.split_at_mut(chunk.len() - TAG_SIZE);
+    if verify_only {
+        cipher.verify_any(n, ciphertext, tag, last, padded, may_pad)
+    } else {
+        cipher.open_any(n, ciphertext, tag, last, padded, may_pad)
+    }
+}
+
+// Errors from read() carry a StreamError as their inner error, which
+// Error::from recovers. Plaintext is only ever released after the chunk
+// containing it has been authenticated.
+pub struct Decryptor<R: Read> {
+    reader: R,
+    opener: ChunkOpener,
+    // One encrypted chunk followed by a single octet of lookahead, which
+    // decides whether the chunk has to carry the last-chunk flag
+    buffer: Zeroizing<Vec<u8>>,
+    filled: usize,
+    plaintext: Range<usize>,
+    state: State,
+}
+
+impl<R: Read> Decryptor<R> {
+    pub fn new(reader: R, recipient_secret: &ZornIdentitySecret, sender_identity: &ZornIdentity) -> Result<Self, Error> {
+        Self::with_identities(reader, std::slice::from_ref(recipient_secret), sender_identity)
+    }
+
+    pub fn with_identities(mut reader: R, recipient_secrets: &[ZornIdentitySecret], sender_identity: &ZornIdentity) -> Result<Self, Error> {
+        let header = Header::read_from(&mut reader)?;
+        let opener = ChunkOpener::new(&header, header.encoded_len() as u64, PaddingRule::from(header.version), recipient_secrets, sender_identity)?;
+        Ok(Self::with_opener(reader, opener))
+    }
+
+    // A uniform message, see the uniform module
+    pub fn uniform(reader: R, recipient_secret: &ZornIdentitySecret, sender_identity: &ZornIdentity) -> Result<Self, Error> {
+        Self::uniform_with_identities(reader, std::slice::from_ref(recipient_secret), sender_identity)
+    }
+
+    pub fn uniform_with_identities(mut reader: R, recipient_secrets: &[ZornIdentitySecret], sender_identity: &ZornIdentity) -> Result<Self, Error> {
+        let header = uniform::read_header(&mut reader)?;
+        let opener = ChunkOpener::new(&header, REPRESENTATIVE_LEN as u64, PaddingRule::Optional, recipient_secrets, sender_identity)?;
+        Ok(Self::with_opener(reader, opener))
+    }
+
+    fn with_opener(reader: R, opener: ChunkOpener) -> Self {
+        Decryptor {
+            reader,
+            opener,
+            buffer: Zeroizing::new(vec![0u8; ENCRYPTED_CHUNK_SIZE + 1]),
+            filled: 0,
+            plaintext: 0..0,
+            state: State::Reading,
+        }
+    }
+
+    pub fn authenticated_bytes(&self) -> u64 {
+        self.opener.authenticated_bytes()
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn fill_chunk(&mut self) -> io::Result<()> {
+        if self.filled == self.buffer.len() {
+            self.buffer[0] = self.buffer[ENCRYPTED_CHUNK_SIZE];
+            self.filled = 1;
+        }
+
+        while self.filled < self.buffer.len() {
+            match self.reader.read(&mut self.buffer[self.filled..]) {
+                Ok(0) => break,
+                Ok(n) => self.filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn open_chunk(&mut self) -> io::Result<()> {
+        self.fill_chunk()?;
+
+        let last = self.filled < self.buffer.len();
+        let len = self.filled.min(ENCRYPTED_CHUNK_SIZE);
+        let (plaintext_len, state) = self.opener.open(&mut self.buffer[..len], last)?;
+        self.plaintext = 0..plaintext_len;
+        self.state = state;
+        Ok(())
+    }
+}
+
+impl<R: Read> BufRead for Decryptor<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.plaintext.is_empty() {
+            match self.state {
+                State::Reading => self.open_chunk()?,
+                State::Finished => break,
+                State::Failed(e) => return Err(e.into()),
+            }
+        }
+        Ok(&self.buffer[self.plaintext.clone()])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.plaintext.start = (self.plaintext.start + amt).min(self.plaintext.end);
+    }
+}
+
+impl<R: Read> Read for Decryptor<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let available = self.fill_buf()?;
+        let n = buf.len().min(available.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+// Authenticates a whole message without decrypting any of it, which the tags
+// allow because they only cover the ciphertext
+pub struct Verifier<R: Read> {
+    // Never hands out its buffer, which only ever holds ciphertext
+    decryptor: Decryptor<R>,
+}
+
+impl<R: Read> Verifier<R> {
+    pub fn new(reader: R, recipient_secret: &ZornIdentitySecret, sender_identity: &ZornIdentity) -> Result<Self, Error> {
+        Self::with_identities(reader, std::slice::from_ref(recipient_secret), sender_identity)
+    }
+
+    pub fn with_identities(mut reader: R, recipient_secrets: &[ZornIdentitySecret], sender_identity: &ZornIdentity) -> Result<Self, Error> {
+        let header = Header::read_from(&mut reader)?;
+        let opener = ChunkOpener::new(&header, header.encoded_len() as u64, PaddingRule::from(header.version), recipient_secrets, sender_identity)?.verify_only();
+        Ok(Verifier { decryptor: Decryptor::with_opener(reader, opener) })
+    }
+
+    // A uniform message, see the uniform module
+    pub fn uniform(reader: R, recipient_secret: &ZornIdentitySecret, sender_identity: &ZornIdentity) -> Result<Self, Error> {
+        Self::uniform_with_identities(reader, std::slice::from_ref(recipient_secret), sender_identity)
+    }
+
+    pub fn uniform_with_identities(mut reader: R, recipient_secrets: &[ZornIdentitySecret], sender_identity: &ZornIdentity) -> Result<Self, Error> {
+        let header = uniform::read_header(&mut reader)?;
+        let opener = ChunkOpener::new(&header, REPRESENTATIVE_LEN as u64, PaddingRule::Optional, recipient_secrets, sender_identity)?.verify_only();
+        Ok(Verifier { decryptor: Decryptor::with_opener(reader, opener) })
+    }
+
+    // Returns the plaintext length of a complete and authentic message
+    pub fn verify(&mut self) -> Result<u64, Error> {
+        loop {
+            let len = self.decryptor.fill_buf()?.len();
+            if len == 0 {
+                return Ok(self.decryptor.authenticated_bytes());
+            }
+            self.decryptor.consume(len);
+        }
+    }
+
+    // After a failure, this is the index of the first chunk that is bad or missing
+    pub fn verified_chunks(&self) -> u64 {
+        self.decryptor.opener.index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::assert_matches::assert_matches;
+    use std::io::{Read, Write};
+    use aead::{AeadInPlace, KeyInit, Tag, generic_array::GenericArray};
+    use rand_chacha::ChaCha20Rng;
+    use rand_core::{OsRng, SeedableRng};
+
+    use crate::chunk::{chunk_nonce, ChunkError, PayloadCipher};
+    use crate::error::Error;
+    use crate::header::{Header, Version};
+    use crate::identity::{ZornIdentity, ZornIdentitySecret};
+    use crate::key_exchange::SharedSecret;
+    use crate::padding::{Padding, PADDING_MARKER};
+    use crate::test_util::{decrypt, encrypt, stream_error};
+    use crate::xchacha20_blake3::XChaCha20Blake3;
+
+    use super::{Decryptor, Encryptor, StreamError, Verifier, ciphertext_len, ciphertext_offset, plaintext_len, CHUNK_SIZE, ENCRYPTED_CHUNK_SIZE, TAG_SIZE};
+
+    use proptest::{proptest, prelude::{any, ProptestConfig}, collection::vec};
+
+    fn encrypt_padded(sender_secret: &ZornIdentitySecret, recipient_secret: &ZornIdentitySecret, plaintext: &[u8], padding: Padding) -> Vec<u8> {
+        let mut e = Encryptor::new(Vec::new(), sender_secret, &ZornIdentity::from(recipient_secret)).expect("Writing to a Vec should succeed, but")
+            .with_padding(padding);
+        e.write_all(plaintext).expect("Writing to a Vec should succeed, but");
+        e.finish().expect("Writing to a Vec should succeed, but")
+    }
+
+    // Seals every plaintext as a chunk with the given padding flag, the last one as the final chunk
+    fn seal_chunks(sender_secret: &ZornIdentitySecret, recipient_secret: &ZornIdentitySecret, version: Version, chunks: &[(&[u8], bool)]) -> Vec<u8> {
+        let (header, mut cipher) = PayloadCipher::for_sender(OsRng, sender_secret, &ZornIdentity::from(recipient_secret)).expect("Key exchange should succeed, but");
+        let mut file = Header { version, ..header }.to_bytes().to_vec();
+        for (i, &(plaintext, padded)) in chunks.iter().enumerate() {
+            let mut chunk = plaintext.to_vec();
+            let tag = cipher.seal(&mut chunk, i + 1 == chunks.len(), padded).expect("Chunk should be valid, but");
+            file.extend_from_slice(&chunk);
+            file.extend_from_slice(&tag);
+        }
+        file
+    }
+
+    // Decrypts chunk by chunk following the spec, independently of the stream decryptor
+    fn reference_decrypt(sender_secret: &ZornIdentitySecret, recipient_secret: &ZornIdentitySecret, file: &[u8]) -> Vec<u8> {
+        let mut reader = file;
+        let header = Header::read_from(&mut reader).expect("Header should be valid, but");
+        let shared_secret = SharedSecret::recipient(recipient_secret, &ZornIdentity::from(sender_secret), &header.ephemeral_identity).expect("Key exchange should be contributory, but");
+        let aead = XChaCha20Blake3::new(GenericArray::from_slice(shared_secret.as_bytes()));
+        let ad = [header.ephemeral_identity.to_bytes(), ZornIdentity::from(sender_secret).to_bytes(), ZornIdentity::from(recipient_secret).to_bytes()].concat();
+
+        let chunks: Vec<_> = reader.chunks(ENCRYPTED_CHUNK_SIZE).collect();
+        let mut plaintext = Vec::new();
+        for (n, chunk) in chunks.iter().enumerate() {
+            let (ciphertext, tag) = chunk.split_at(chunk.len() - TAG_SIZE);
+            let mut buf = ciphertext.to_vec();
+            aead.decrypt_in_place_detached(&chunk_nonce(n as u64, n + 1 == chunks.len(), false), &ad, &mut buf, Tag::<XChaCha20Blake3>::from_slice(tag))
+                .expect("Chunk should authenticate, but");
+            plaintext.extend_from_slice(&buf);
+        }
+        plaintext
+    }
+
+    #[test]
+    fn encrypt_chunk_boundaries() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+
+        for (len, payload_len) in [
+            (0, TAG_SIZE),
+            (1, 1 + TAG_SIZE),
+            (CHUNK_SIZE, ENCRYPTED_CHUNK_SIZE),
+            (CHUNK_SIZE + 1, ENCRYPTED_CHUNK_SIZE + 1 + TAG_SIZE),
+            (3 * CHUNK_SIZE, 3 * ENCRYPTED_CHUNK_SIZE),
+        ] {
+            let plaintext = vec![0x5au8; len];
+            let file = encrypt(&sender_secret, &recipient_secret, &plaintext);
+            assert_eq!(file.len(), 55 + payload_len);
+            assert_eq!(ciphertext_len(len as u64), payload_len as u64);
+            assert_eq!(plaintext_len(payload_len as u64), Some(len as u64));
+            assert_eq!(reference_decrypt(&sender_secret, &recipient_secret, &file), plaintext);
+        }
+    }
+
+    #[test]
+    fn encrypt_with_seeded_rng_is_reproducible() {
+        let sender_secret = ZornIdentitySecret::from([1u8; 32]);
+        let recipient_identity = ZornIdentity::from(&ZornIdentitySecret::from([2u8; 32]));
+        let encrypt_seeded = |seed| {
+            let mut e = Encryptor::with_rng(Vec::new(), ChaCha20Rng::seed_from_u64(seed), &sender_secret, &recipient_identity).expect("Writing to a Vec should succeed, but");
+            e.write_all(b"reproducible").expect("Writing to a Vec should succeed, but");
+            e.finish().expect("Writing to a Vec should succeed, but")
+        };
+
+        assert_eq!(encrypt_seeded(7), encrypt_seeded(7));
+        assert_ne!(encrypt_seeded(7)[23..55], encrypt_seeded(8)[23..55]);
+    }
+
+    #[test]
+    fn length_and_offset_helpers() {
+        for invalid in [0, TAG_SIZE - 1, ENCRYPTED_CHUNK_SIZE + TAG_SIZE, ENCRYPTED_CHUNK_SIZE + TAG_SIZE - 1] {
+            assert_eq!(plaintext_len(invalid as u64), None);
+        }
+
+        assert_eq!(ciphertext_offset(0), 0);
+        assert_eq!(ciphertext_offset(CHUNK_SIZE as u64 - 1), CHUNK_SIZE as u64 - 1);
+        assert_eq!(ciphertext_offset(CHUNK_SIZE as u64), ENCRYPTED_CHUNK_SIZE as u64);
+        assert_eq!(ciphertext_offset(2 * CHUNK_SIZE as u64 + 7), 2 * ENCRYPTED_CHUNK_SIZE as u64 + 7);
+    }
+
+    #[test]
+    fn decrypt_chunk_boundaries() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+
+        for len in [0, 1, CHUNK_SIZE - 1, CHUNK_SIZE, CHUNK_SIZE + 1, 3 * CHUNK_SIZE] {
+            let plaintext: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let file = encrypt(&sender_secret, &recipient_secret, &plaintext);
+            assert_eq!(decrypt(&sender_secret, &recipient_secret, &file), (plaintext, Ok(())));
+        }
+    }
+
+    #[test]
+    fn decrypt_truncated_at_chunk_boundary() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+        let plaintext = vec![0x5au8; 3 * CHUNK_SIZE];
+        let file = encrypt(&sender_secret, &recipient_secret, &plaintext);
+
+        for chunks in 0..3 {
+            let (released, result) = decrypt(&sender_secret, &recipient_secret, &file[..55 + chunks * ENCRYPTED_CHUNK_SIZE]);
+            assert_eq!(released, &plaintext[..chunks * CHUNK_SIZE]);
+            assert_eq!(result, Err(StreamError::Truncated { authenticated: (chunks * CHUNK_SIZE) as u64 }));
+        }
+    }
+
+    #[test]
+    fn decrypt_truncated_within_chunk() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+        let plaintext = vec![0x5au8; 2 * CHUNK_SIZE];
+        let file = encrypt(&sender_secret, &recipient_secret, &plaintext);
+
+        let (released, result) = decrypt(&sender_secret, &recipient_secret, &file[..55 + ENCRYPTED_CHUNK_SIZE + TAG_SIZE - 1]);
+        assert_eq!(released, &plaintext[..CHUNK_SIZE]);
+        assert_eq!(result, Err(StreamError::Truncated { authenticated: CHUNK_SIZE as u64 }));
+
+        let (released, result) = decrypt(&sender_secret, &recipient_secret, &file[..55 + ENCRYPTED_CHUNK_SIZE + 100]);
+        assert_eq!(released, &plaintext[..CHUNK_SIZE]);
+        assert_eq!(result, Err(StreamError::Authentication { chunk: 1, offset: (55 + ENCRYPTED_CHUNK_SIZE) as u64 }));
+    }
+
+    #[test]
+    fn decrypt_trailing_data() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+        let mut file = encrypt(&sender_secret, &recipient_secret, &vec![0x5au8; CHUNK_SIZE]);
+        file.push(0);
+
+        assert_eq!(decrypt(&sender_secret, &recipient_secret, &file), (Vec::new(), Err(StreamError::TrailingData)));
+    }
+
+    #[test]
+    fn decrypt_rejects_modified_chunks() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+        let plaintext = vec![0x5au8; 3 * CHUNK_SIZE];
+        let file = encrypt(&sender_secret, &recipient_secret, &plaintext);
+
+        let mut flipped = file.clone();
+        flipped[55 + 2 * ENCRYPTED_CHUNK_SIZE + 7] ^= 1;
+        let (released, result) = decrypt(&sender_secret, &recipient_secret, &flipped);
+        assert_eq!(released, &plaintext[..2 * CHUNK_SIZE]);
+        assert_eq!(result, Err(StreamError::Authentication { chunk: 2, offset: (55 + 2 * ENCRYPTED_CHUNK_SIZE) as u64 }));
+
+        let mut reordered = file[..55].to_vec();
+        reordered.extend_from_slice(&file[55 + ENCRYPTED_CHUNK_SIZE..55 + 2 * ENCRYPTED_CHUNK_SIZE]);
+        reordered.extend_from_slice(&file[55..55 + ENCRYPTED_CHUNK_SIZE]);
+        reordered.extend_from_slice(&file[55 + 2 * ENCRYPTED_CHUNK_SIZE..]);
+        assert_eq!(decrypt(&sender_secret, &recipient_secret, &reordered), (Vec::new(), Err(StreamError::Authentication { chunk: 0, offset: 55 })));
+
+        let other_sender = ZornIdentitySecret::new(OsRng);
+        assert_eq!(decrypt(&other_sender, &recipient_secret, &file), (Vec::new(), Err(StreamError::Authentication { chunk: 0, offset: 55 })));
+    }
+
+    #[test]
+    fn decrypt_with_identities() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+        let plaintext = vec![0x5au8; CHUNK_SIZE + 1];
+        let file = encrypt(&sender_secret, &recipient_secret, &plaintext);
+
+        let secrets = [ZornIdentitySecret::new(OsRng), recipient_secret, ZornIdentitySecret::new(OsRng)];
+        let mut d = Decryptor::with_identities(&file[..], &secrets, &ZornIdentity::from(&sender_secret)).expect("Header should be valid, but");
+        let mut decrypted = Vec::new();
+        d.read_to_end(&mut decrypted).expect("Decryption should succeed, but");
+        assert_eq!(decrypted, plaintext);
+
+        let mut d = Decryptor::with_identities(&file[..], &secrets[2..], &ZornIdentity::from(&sender_secret)).expect("Header should be valid, but");
+        assert_eq!(stream_error(d.read_to_end(&mut Vec::new()).expect_err("The first chunk should fail to authenticate, but")), StreamError::Authentication { chunk: 0, offset: 55 });
+    }
+
+    #[test]
+    fn decrypt_empty_payload() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+        let file = encrypt(&sender_secret, &recipient_secret, &[]);
+
+        assert_eq!(decrypt(&sender_secret, &recipient_secret, &file[..55]), (Vec::new(), Err(StreamError::Truncated { authenticated: 0 })));
+        assert_eq!(decrypt(&sender_secret, &recipient_secret, &file[..55 + TAG_SIZE - 1]), (Vec::new(), Err(StreamError::Truncated { authenticated: 0 })));
+    }
+
+    #[test]
+    fn verify_reports_first_bad_chunk() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+        let file = encrypt(&sender_secret, &recipient_secret, &vec![0x5au8; 2 * CHUNK_SIZE + 1]);
+        let verify = |file: &[u8]| {
+            let mut v = Verifier::new(file, &recipient_secret, &ZornIdentity::from(&sender_secret)).expect("Header should be valid, but");
+            (v.verify(), v.verified_chunks())
+        };
+
+        assert_matches!(verify(&file), (Ok(len), 3) if len == 2 * CHUNK_SIZE as u64 + 1);
+
+        let mut flipped = file.clone();
+        flipped[55 + ENCRYPTED_CHUNK_SIZE + 7] ^= 1;
+        assert_matches!(verify(&flipped), (Err(Error::Authentication { chunk: 1, .. }), 1));
+        assert_matches!(verify(&file[..55 + 2 * ENCRYPTED_CHUNK_SIZE]), (Err(Error::Truncated { .. }), 2));
+
+        let mut trailing = file.clone();
+        trailing.push(0);
+        assert_matches!(verify(&trailing), (Err(Error::Authentication { chunk: 2, .. }), 2));
+    }
+
+    #[test]
+    fn padded_roundtrip() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+
+        for padding in [Padding::Padme, Padding::To(3 * CHUNK_SIZE as u64 + 5)] {
+            // The plaintext ends in a zero for 257 octets and in the marker for 129
+            for len in [0, 1, 129, 257, CHUNK_SIZE - 1, CHUNK_SIZE, CHUNK_SIZE + 1, 2 * CHUNK_SIZE + 100] {
+                let plaintext: Vec<u8> = (0..len).map(|i| i as u8).collect();
+                let file = encrypt_padded(&sender_secret, &recipient_secret, &plaintext, padding);
+                let padded_len = padding.padded_len(len as u64).expect("Plaintext should fit the padding, but");
+                assert_eq!(file.len() as u64 - 55, ciphertext_len(padded_len));
+                assert_eq!(decrypt(&sender_secret, &recipient_secret, &file), (plaintext, Ok(())));
+
+                let mut v = Verifier::new(&file[..], &recipient_secret, &ZornIdentity::from(&sender_secret)).expect("Header should be valid, but");
+                assert_matches!(v.verify(), Ok(verified) if verified == len as u64);
+            }
+        }
+
+        let padme_len = |len| encrypt_padded(&sender_secret, &recipient_secret, &vec![0x5au8; len], Padding::Padme).len();
+        assert_eq!(padme_len(1000), padme_len(1020));
+    }
+
+    #[test]
+    fn padding_to_a_size_rejects_longer_messages() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+        let mut e = Encryptor::new(Vec::new(), &sender_secret, &ZornIdentity::from(&recipient_secret)).expect("Writing to a Vec should succeed, but")
+            .with_padding(Padding::To(10));
+
+        e.write_all(&[0x5a; 9]).expect("Nine octets and the marker should fit, but");
+        let e = e.write_all(&[0x5a]).expect_err("Ten octets and the marker should not fit, but");
+        assert_matches!(Error::from(e), Error::Chunk(ChunkError::TooLongForPadding));
+    }
+
+    #[test]
+    fn decrypt_rejects_malformed_padding() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+        let data = vec![0x5au8; CHUNK_SIZE];
+        let mut marked = vec![0u8; CHUNK_SIZE];
+        marked[..100].fill(0x5a);
+        marked[100] = PADDING_MARKER;
+        let zeros = vec![0u8; CHUNK_SIZE];
+        let decrypt_chunks = |chunks: &[(&[u8], bool)]| decrypt(&sender_secret, &recipient_secret, &seal_chunks(&sender_secret, &recipient_secret, Version::V2, chunks));
+
+        assert_eq!(decrypt_chunks(&[(&data, false), (&marked, true), (&zeros, true), (&[0; 7], true)]), ([&data[..], &marked[..100]].concat(), Ok(())));
+        assert_eq!(decrypt_chunks(&[(&data, false), (&marked[..101], true)]), ([&data[..], &marked[..100]].concat(), Ok(())));
+        assert_eq!(decrypt_chunks(&[(&data, false), (&[], true)]), (data.clone(), Err(StreamError::Padding { chunk: 1 })));
+        assert_eq!(decrypt_chunks(&[(&data, true)]), (Vec::new(), Err(StreamError::Padding { chunk: 0 })));
+        assert_eq!(decrypt_chunks(&[(&marked, true), (&[0, 1], true)]), (marked[..100].to_vec(), Err(StreamError::Padding { chunk: 1 })));
+        assert_eq!(decrypt_chunks(&[(&marked, true), (&[1, 2], false)]),
+            (marked[..100].to_vec(), Err(StreamError::Authentication { chunk: 1, offset: 55 + ENCRYPTED_CHUNK_SIZE as u64 })));
+    }
+
+    #[test]
+    fn decrypt_checks_padding_against_the_version() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+        let data = vec![0x5au8; CHUNK_SIZE];
+        let mut marked = vec![0u8; CHUNK_SIZE];
+        marked[..100].fill(0x5a);
+        marked[100] = PADDING_MARKER;
+        let decrypt_chunks = |version, chunks: &[(&[u8], bool)]| decrypt(&sender_secret, &recipient_secret, &seal_chunks(&sender_secret, &recipient_secret, version, chunks));
+
+        // A v1 message has no padding, and a v2 message always ends in some
+        assert_eq!(decrypt_chunks(Version::V1, &[(&data, false), (&marked, true)]),
+            (data.clone(), Err(StreamError::Authentication { chunk: 1, offset: 55 + ENCRYPTED_CHUNK_SIZE as u64 })));
+        assert_eq!(decrypt_chunks(Version::V2, &[(&data, false), (&data, false)]), (data.clone(), Err(StreamError::Padding { chunk: 1 })));
+        assert_eq!(decrypt_chunks(Version::V2, &[(&[], false)]), (Vec::new(), Err(StreamError::Padding { chunk: 0 })));
+        assert_eq!(decrypt_chunks(Version::V1, &[(&data, false), (&data, false)]), ([&data[..], &data[..]].concat(), Ok(())));
+    }
+
+    #[test]
+    fn padded_messages_are_v2() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+        assert!(encrypt(&sender_secret, &recipient_secret, b"message").starts_with(b"zorn-encryption.org/v1\n"));
+        assert!(encrypt_padded(&sender_secret, &recipient_secret, b"message", Padding::Padme).starts_with(b"zorn-encryption.org/v2\n"));
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+        #[test]
+        fn length_helpers_roundtrip(len in any::<u32>()) {
+            assert_eq!(plaintext_len(ciphertext_len(len as u64)), Some(len as u64));
+            assert!(ciphertext_offset(len as u64) < ciphertext_len(len as u64 + 1));
+        }
+
+        #[test]
+        fn encrypt_split_writes(
+                sk in any::<ZornIdentitySecret>(),
+                rk in any::<ZornIdentitySecret>(),
+                plaintext in vec(any::<u8>(), 0..=2 * CHUNK_SIZE + 1),
+                split in 1..=CHUNK_SIZE + 1) {
+            let mut e = Encryptor::new(Vec::new(), &sk, &ZornIdentity::from(&rk)).expect("Writing to a Vec should succeed, but");
+            for part in plaintext.chunks(split) {
+                e.write_all(part).expect("Writing to a Vec should succeed, but");
+            }
+            let file = e.finish().expect("Writing to a Vec should succeed, but");
+            assert_eq!(reference_decrypt(&sk, &rk, &file), plaintext);
+        }
+
+        #[test]
+        fn decrypt_split_reads(
+                sk in any::<ZornIdentitySecret>(),
+                rk in any::<ZornIdentitySecret>(),
+                plaintext in vec(any::<u8>(), 0..=2 * CHUNK_SIZE + 1),
+                split in 1..=CHUNK_SIZE + 1) {
+            let file = encrypt(&sk, &rk, &plaintext);
+            let mut d = Decryptor::new(&file[..], &rk, &ZornIdentity::from(&sk)).expect("Header should be valid, but");
+            let mut decrypted = Vec::new();
+            let mut buf = vec![0u8; split];
+            loop {
+                match d.read(&mut buf).expect("Decryption should succeed, but") {
+                    0 => break,
+                    n => decrypted.extend_from_slice(&buf[..n]),
+                }
+            }
+            assert_eq!(decrypted, plaintext);
+        }
+    }
+}
@@ -0,0 +1,24 @@
+// Helpers shared by the tests of the streaming interfaces
+use std::io::{self, Read, Write};
+
+use crate::identity::{ZornIdentity, ZornIdentitySecret};
+use crate::stream::{Decryptor, Encryptor, StreamError};
+
+pub(crate) fn encrypt(sender_secret: &ZornIdentitySecret, recipient_secret: &ZornIdentitySecret, plaintext: &[u8]) -> Vec<u8> {
+    let mut e = Encryptor::new(Vec::new(), sender_secret, &ZornIdentity::from(recipient_secret)).expect("Writing to a Vec should succeed, but");
+    e.write_all(plaintext).expect("Writing to a Vec should succeed, but");
+    e.finish().expect("Writing to a Vec should succeed, but")
+}
+
+// Everything the stream decryptor released, and how it stopped
+pub(crate) fn decrypt(sender_secret: &ZornIdentitySecret, recipient_secret: &ZornIdentitySecret, file: &[u8]) -> (Vec<u8>, Result<(), StreamError>) {
+    let mut d = Decryptor::new(file, recipient_secret, &ZornIdentity::from(sender_secret)).expect("Header should be valid, but");
+    let mut plaintext = Vec::new();
+    let result = d.read_to_end(&mut plaintext).map(|_| ()).map_err(stream_error);
+    assert_eq!(d.authenticated_bytes(), plaintext.len() as u64);
+    (plaintext, result)
+}
+
+pub(crate) fn stream_error(e: io::Error) -> StreamError {
+    *e.into_inner().and_then(|e| e.downcast().ok()).expect("Error should be a StreamError, but")
+}
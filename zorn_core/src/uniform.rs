@@ -0,0 +1,163 @@
+// A uniform message is a v1 message with the header replaced by an Elligator2
+// representative of the ephemeral identity. The representative and the
+// chunks are indistinguishable from random bytes, so nothing about the
+// message gives it away as a zorn message, or as anything at all. Any 32
+// bytes are a representative, so a uniform message with the wrong sender or
+// recipient only fails to authenticate.
+use std::io::{self, Read};
+
+use curve25519_dalek::montgomery::MontgomeryPoint;
+use x25519_dalek::PublicKey;
+
+use crate::header::{Header, HeaderError};
+
+pub const REPRESENTATIVE_LEN: usize = 32;
+
+pub fn ephemeral_identity(representative: &[u8; REPRESENTATIVE_LEN]) -> PublicKey {
+    PublicKey::from(MontgomeryPoint::from_representative(representative).to_bytes())
+}
+
+// Reads the representative in place of a header, giving the v1 header the
+// message stands for. The payload starts right after the representative.
+pub fn read_header<R: Read>(mut reader: R) -> Result<Header, HeaderError> {
+    let mut representative = [0u8; REPRESENTATIVE_LEN];
+    reader.read_exact(&mut representative).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => HeaderError::Truncated,
+        _ => HeaderError::Io(e),
+    })?;
+    Ok(Header::new(ephemeral_identity(&representative)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::assert_matches::assert_matches;
+    use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+    use curve25519_dalek::montgomery::MontgomeryPoint;
+    use rand_core::OsRng;
+
+    use crate::header::{Header, HeaderError};
+    use crate::identity::{ZornIdentity, ZornIdentitySecret};
+    use crate::seekable::SeekableDecryptor;
+    use crate::stream::{ciphertext_len, Decryptor, Encryptor, StreamError, Verifier, ENCRYPTED_CHUNK_SIZE};
+    use crate::test_util::stream_error;
+
+    use super::{ephemeral_identity, read_header, REPRESENTATIVE_LEN};
+
+    use proptest::{proptest, prelude::ProptestConfig, collection::vec};
+
+    fn encrypt_uniform(sender_secret: &ZornIdentitySecret, recipient_secret: &ZornIdentitySecret, plaintext: &[u8]) -> Vec<u8> {
+        let mut e = Encryptor::uniform(Vec::new(), sender_secret, &ZornIdentity::from(recipient_secret)).expect("Writing to a Vec should succeed, but");
+        e.write_all(plaintext).expect("Writing to a Vec should succeed, but");
+        e.finish().expect("Writing to a Vec should succeed, but")
+    }
+
+    #[test]
+    fn uniform_message_has_no_header() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+
+        let file = encrypt_uniform(&sender_secret, &recipient_secret, b"hello");
+        assert_eq!(file.len() as u64, REPRESENTATIVE_LEN as u64 + ciphertext_len(5));
+
+        let mut reader = &file[..];
+        let header = read_header(&mut reader).expect("Reading from a slice should succeed, but");
+        assert_eq!(header, Header::new(ephemeral_identity(file[..REPRESENTATIVE_LEN].try_into().expect("A representative is 32 octets, but"))));
+        assert_eq!(reader, &file[REPRESENTATIVE_LEN..]);
+    }
+
+    // Without the low order component, every ephemeral identity would be in
+    // the prime order subgroup and the high bit would always be clear
+    #[test]
+    fn uniform_ephemeral_identities_cover_the_whole_curve() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+
+        let representatives: Vec<[u8; REPRESENTATIVE_LEN]> = (0..64)
+            .map(|_| encrypt_uniform(&sender_secret, &recipient_secret, b"")[..REPRESENTATIVE_LEN].try_into().expect("A representative is 32 octets, but"))
+            .collect();
+        let torsion_free = representatives.iter()
+            .filter(|r| MontgomeryPoint::from_representative(r).to_edwards(0).expect("Representatives should map to the curve, but").is_torsion_free())
+            .count();
+        let high_bit = representatives.iter().filter(|r| r[31] & 0x80 != 0).count();
+
+        assert!(torsion_free < 32, "{} of 64 ephemeral identities are torsion free", torsion_free);
+        assert!((8..56).contains(&high_bit), "{} of 64 representatives have the high bit set", high_bit);
+    }
+
+    #[test]
+    fn uniform_header_rejects_short_messages() {
+        assert_matches!(read_header(&[0u8; REPRESENTATIVE_LEN - 1][..]), Err(HeaderError::Truncated));
+    }
+
+    #[test]
+    fn uniform_message_fails_to_authenticate_for_others() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+        let file = encrypt_uniform(&sender_secret, &recipient_secret, b"hello");
+
+        let mut decryptor = Decryptor::uniform(&file[..], &ZornIdentitySecret::new(OsRng), &ZornIdentity::from(&sender_secret))
+            .expect("Key exchange should succeed, but");
+        let e = decryptor.read_to_end(&mut Vec::new()).expect_err("Decryption with the wrong key should fail, but");
+        assert_matches!(e.into_inner().map(|e| e.downcast::<StreamError>()), Some(Ok(e)) if matches!(*e, StreamError::Authentication { chunk: 0, .. }));
+    }
+
+    // Offsets are those in the uniform message, not in the v1 message it
+    // stands for
+    #[test]
+    fn uniform_message_reports_offsets_in_the_file() {
+        let sender_secret = ZornIdentitySecret::new(OsRng);
+        let recipient_secret = ZornIdentitySecret::new(OsRng);
+        let mut file = encrypt_uniform(&sender_secret, &recipient_secret, &vec![0u8; 200_000]);
+        let offset = REPRESENTATIVE_LEN + ENCRYPTED_CHUNK_SIZE;
+        file[offset] ^= 1;
+
+        let mut decryptor = Decryptor::uniform(&file[..], &recipient_secret, &ZornIdentity::from(&sender_secret))
+            .expect("Key exchange should succeed, but");
+        let e = decryptor.read_to_end(&mut Vec::new()).expect_err("Decryption of a tampered chunk should fail, but");
+        assert_matches!(stream_error(e), StreamError::Authentication { chunk: 1, offset: o } if o == offset as u64);
+
+        let mut verifier = Verifier::uniform(&file[..], &recipient_secret, &ZornIdentity::from(&sender_secret))
+            .expect("Key exchange should succeed, but");
+        let e = verifier.verify().expect_err("Verification of a tampered chunk should fail, but");
+        assert_matches!(e, crate::Error::Authentication { chunk: 1, offset: o } if o == offset as u64);
+
+        let mut decryptor = SeekableDecryptor::uniform(Cursor::new(&file), &recipient_secret, &ZornIdentity::from(&sender_secret))
+            .expect("The final chunk should authenticate, but");
+        let e = decryptor.read_to_end(&mut Vec::new()).expect_err("Decryption of a tampered chunk should fail, but");
+        assert_matches!(stream_error(e), StreamError::Authentication { chunk: 1, offset: o } if o == offset as u64);
+
+        #[cfg(feature = "parallel")]
+        {
+            use crate::parallel::{ParallelDecryptor, DEFAULT_CHUNKS_IN_FLIGHT};
+            let mut decryptor = ParallelDecryptor::uniform(&file[..], &recipient_secret, &ZornIdentity::from(&sender_secret), DEFAULT_CHUNKS_IN_FLIGHT)
+                .expect("Key exchange should succeed, but");
+            let e = decryptor.read_to_end(&mut Vec::new()).expect_err("Decryption of a tampered chunk should fail, but");
+            assert_matches!(stream_error(e), StreamError::Authentication { chunk: 1, offset: o } if o == offset as u64);
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(16))]
+        #[test]
+        fn uniform_roundtrip(plaintext in vec(0u8.., 0..200_000), offset in 0u64..200_000) {
+            let sender_secret = ZornIdentitySecret::new(OsRng);
+            let recipient_secret = ZornIdentitySecret::new(OsRng);
+            let file = encrypt_uniform(&sender_secret, &recipient_secret, &plaintext);
+
+            let mut decrypted = Vec::new();
+            Decryptor::uniform(&file[..], &recipient_secret, &ZornIdentity::from(&sender_secret))
+                .expect("Key exchange should succeed, but")
+                .read_to_end(&mut decrypted)
+                .expect("Decryption should succeed, but");
+            assert_eq!(decrypted, plaintext);
+
+            let mut decryptor = SeekableDecryptor::uniform(Cursor::new(&file), &recipient_secret, &ZornIdentity::from(&sender_secret))
+                .expect("The final chunk should authenticate, but");
+            let offset = offset.min(plaintext.len() as u64);
+            decryptor.seek(SeekFrom::Start(offset)).expect("Seeking should succeed, but");
+            let mut tail = Vec::new();
+            decryptor.read_to_end(&mut tail).expect("Decryption should succeed, but");
+            assert_eq!(tail, &plaintext[offset as usize..]);
+        }
+    }
+}
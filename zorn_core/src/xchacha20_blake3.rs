@@ -32,6 +32,26 @@ impl AeadCore for XChaCha20Blake3 {
 }
 
 impl XChaCha20Blake3 {
+    fn mac(&self, nonce: &Nonce<Self>, associated_data: &[u8], ciphertext: &[u8]) -> blake3::Hash {
+        let mut mac = self.mac.clone();
+        mac.update(nonce);
+        mac.update(associated_data);
+        mac.update(ciphertext);
+        mac.update(&associated_data.len().to_le_bytes());
+        mac.update(&ciphertext.len().to_le_bytes());
+        mac.finalize()
+    }
+
+    // The tag only covers the ciphertext, so checking it needs no keystream
+    pub fn verify_detached(&self, nonce: &Nonce<Self>, associated_data: &[u8], ciphertext: &[u8], tag: &aead::Tag<Self>) -> aead::Result<()> {
+        // blake3::Hash implements a constant time Eq for comparisons with [u8; 32]
+        if self.mac(nonce, associated_data, ciphertext) == *tag.as_slice() {
+            Ok(())
+        } else {
+            Err(Error)
+        }
+    }
+
     fn derive_cipher_key(key: &[u8]) -> Zeroizing<[u8; 32]> {
         Zeroizing::new(blake3::derive_key(CIPHER_KEY_CONTEXT, key))
     }
@@ -64,15 +84,7 @@ impl AeadInPlace for XChaCha20Blake3 {
         buffer: &mut [u8],
     ) -> aead::Result<aead::Tag<Self>> {
         XChaCha20::new(&self.cipher_key, &nonce).try_apply_keystream(buffer).map_err(|_| Error)?;
-
-        let mut mac = self.mac.clone();
-        mac.update(nonce);
-        mac.update(associated_data);
-        mac.update(buffer);
-        mac.update(&associated_data.len().to_le_bytes());
-        mac.update(&buffer.len().to_le_bytes());
-
-        Ok(GenericArray::clone_from_slice(mac.finalize().as_bytes()))
+        Ok(GenericArray::clone_from_slice(self.mac(nonce, associated_data, buffer).as_bytes()))
     }
 
     fn decrypt_in_place_detached(
@@ -82,19 +94,8 @@ impl AeadInPlace for XChaCha20Blake3 {
         buffer: &mut [u8],
         tag: &aead::Tag<Self>,
     ) -> aead::Result<()> {
-        let mut mac = self.mac.clone();
-        mac.update(nonce);
-        mac.update(associated_data);
-        mac.update(buffer);
-        mac.update(&associated_data.len().to_le_bytes());
-        mac.update(&buffer.len().to_le_bytes());
-
-        // blake3::Hash implements a constant time Eq for comparisons with [u8; 32]
-        if mac.finalize() == *tag.as_slice() {
-            XChaCha20::new(&self.cipher_key, &nonce).try_apply_keystream(buffer).map_err(|_| Error)
-        } else {
-            Err(Error)
-        }
+        self.verify_detached(nonce, associated_data, buffer, tag)?;
+        XChaCha20::new(&self.cipher_key, &nonce).try_apply_keystream(buffer).map_err(|_| Error)
     }
 }
 
@@ -208,5 +209,26 @@ mod test {
                 &tag
             ), Err(_));
         }
+
+        #[test]
+        fn verify_agrees_with_decrypt(
+                sk in any::<[u8; 32]>(),
+                nonce in any::<[u8; 24]>(),
+                msg in vec(any::<u8>(), 0..=512),
+                ad in vec(any::<u8>(), 0..=512),
+                flip in any::<Option<u8>>()) {
+            let cipher = XChaCha20Blake3::new(&GenericArray::clone_from_slice(&sk));
+            let nonce = Nonce::<XChaCha20Blake3>::from(nonce);
+
+            let mut encrypted_message = msg.clone();
+            let mut tag = cipher.encrypt_in_place_detached(&nonce, ad.as_slice(), encrypted_message.as_mut_slice()).expect("Impossibru");
+            if let Some(bit) = flip {
+                tag[bit as usize / 8] ^= 1 << (bit % 8);
+            }
+
+            let verified = cipher.verify_detached(&nonce, ad.as_slice(), encrypted_message.as_slice(), &tag);
+            assert_eq!(verified.is_ok(), flip.is_none());
+            assert_eq!(verified.is_ok(), cipher.decrypt_in_place_detached(&nonce, ad.as_slice(), encrypted_message.as_mut_slice(), &tag).is_ok());
+        }
     }
 }
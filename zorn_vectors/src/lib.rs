@@ -0,0 +1,137 @@
+// Schema of the machine-readable test vectors in spec/vectors, shared by the
+// generator and the conformance test
+use std::num::NonZeroU32;
+use rand_core::{impls, CryptoRng, RngCore};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+pub const FORMAT: &str = "zorn-encryption.org/v1";
+pub const VECTORS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../spec/vectors/v1");
+
+pub const AEAD_FILE: &str = "xchacha20_blake3.json";
+pub const KEY_EXCHANGE_FILE: &str = "key_exchange.json";
+pub const FILES_FILE: &str = "files.json";
+pub const ELLIGATOR2_FILE: &str = "elligator2.json";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hex(pub Vec<u8>);
+
+impl Hex {
+    pub fn to_array<const N: usize>(&self) -> [u8; N] {
+        self.0.as_slice().try_into().expect("Vector field should have the right length, but")
+    }
+}
+
+impl From<&[u8]> for Hex {
+    fn from(bytes: &[u8]) -> Self {
+        Hex(bytes.to_vec())
+    }
+}
+
+impl Serialize for Hex {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(&self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for Hex {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        hex::decode(s).map(Hex).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct VectorFile<T> {
+    pub format: String,
+    pub description: String,
+    pub vectors: Vec<T>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExpectedError {
+    NonContributory,
+    Header,
+    Authentication,
+    Truncated,
+    TrailingData,
+    Padding,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AeadVector {
+    pub comment: String,
+    pub key: Hex,
+    pub nonce: Hex,
+    pub ad: Hex,
+    pub plaintext: Hex,
+    pub ciphertext: Hex,
+    pub tag: Hex,
+    pub valid: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct KeyExchangeVector {
+    pub comment: String,
+    pub sender_secret: Hex,
+    pub recipient_secret: Option<Hex>,
+    pub ephemeral_secret: Hex,
+    pub sender_identity: Hex,
+    pub recipient_identity: Hex,
+    pub ephemeral_identity: Hex,
+    pub shared_secret: Option<Hex>,
+    pub error: Option<ExpectedError>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FileVector {
+    pub comment: String,
+    pub sender_secret: Hex,
+    pub recipient_secret: Hex,
+    pub ephemeral_secret: Hex,
+    pub plaintext_len: Option<usize>,
+    pub padded_len: Option<u64>,
+    pub file: Hex,
+    pub error: Option<ExpectedError>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Elligator2Vector {
+    pub comment: String,
+    pub representative: Hex,
+    pub ephemeral_identity: Hex,
+}
+
+// Octet i of every plaintext in files.json is i mod 251, so that no two
+// chunks of a file share their plaintext
+pub fn plaintext(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+// Hands out the ephemeral secret of a vector as the only randomness of an
+// encryption. It is marked CryptoRng to reproduce vectors, nothing else.
+pub struct FixedRng(pub [u8; 32]);
+
+impl RngCore for FixedRng {
+    fn next_u32(&mut self) -> u32 {
+        impls::next_u32_via_fill(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        impls::next_u64_via_fill(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.try_fill_bytes(dest).expect("FixedRng only fills up to 32 octets")
+    }
+
+    // Fails rather than make up octets beyond the secret
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        let secret = self.0.get(..dest.len())
+            .ok_or_else(|| rand_core::Error::from(NonZeroU32::new(rand_core::Error::CUSTOM_START).expect("CUSTOM_START is not zero")))?;
+        dest.copy_from_slice(secret);
+        Ok(())
+    }
+}
+
+impl CryptoRng for FixedRng {}
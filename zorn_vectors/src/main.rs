@@ -0,0 +1,316 @@
+// Regenerates the test vectors in spec/vectors/v1, or in the directory given
+// as the only argument. All inputs are derived from fixed labels, so the output
+// only changes along with the format.
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use aead::{AeadInPlace, KeyInit, generic_array::GenericArray};
+use serde::Serialize;
+use zorn_core::identity::{ZornIdentity, ZornIdentitySecret};
+use zorn_core::key_exchange::SharedSecret;
+use zorn_core::padding::{Padding, PADDING_MARKER};
+use zorn_core::stream::{Encryptor, CHUNK_SIZE};
+use zorn_core::uniform::ephemeral_identity;
+use zorn_core::xchacha20_blake3::XChaCha20Blake3;
+use zorn_vectors::{AeadVector, Elligator2Vector, ExpectedError, FileVector, FixedRng, Hex, KeyExchangeVector, VectorFile, AEAD_FILE, ELLIGATOR2_FILE, FILES_FILE, FORMAT, KEY_EXCHANGE_FILE, VECTORS_DIR, plaintext};
+
+fn bytes<const N: usize>(label: &str) -> [u8; N] {
+    let mut out = [0u8; N];
+    blake3::Hasher::new_derive_key("zorn-encryption.org/v1 test vectors").update(label.as_bytes()).finalize_xof().fill(&mut out);
+    out
+}
+
+fn identity(secret: &[u8; 32]) -> [u8; 32] {
+    *ZornIdentity::from(&ZornIdentitySecret::from(*secret)).as_bytes()
+}
+
+fn write<T: Serialize>(dir: &Path, name: &str, description: &str, vectors: Vec<T>) -> std::io::Result<()> {
+    let file = VectorFile { format: FORMAT.to_string(), description: description.to_string(), vectors };
+    let mut f = fs::File::create(dir.join(name))?;
+    serde_json::to_writer_pretty(&mut f, &file)?;
+    writeln!(f)
+}
+
+fn aead_vector(comment: &str, label: &str, ad_len: usize, plaintext_len: usize) -> AeadVector {
+    let key: [u8; 32] = bytes(&format!("{label} key"));
+    let nonce: [u8; 24] = bytes(&format!("{label} nonce"));
+    let ad = plaintext(ad_len);
+    let mut buffer = plaintext(plaintext_len);
+    let tag = XChaCha20Blake3::new(GenericArray::from_slice(&key))
+        .encrypt_in_place_detached(GenericArray::from_slice(&nonce), &ad, &mut buffer)
+        .expect("Plaintext should not be too long, but");
+    AeadVector {
+        comment: comment.to_string(),
+        key: Hex::from(&key[..]),
+        nonce: Hex::from(&nonce[..]),
+        ad: Hex(ad),
+        plaintext: Hex(plaintext(plaintext_len)),
+        ciphertext: Hex(buffer),
+        tag: Hex::from(tag.as_slice()),
+        valid: true,
+    }
+}
+
+fn aead_vectors() -> Vec<AeadVector> {
+    let mut vectors = vec![
+        aead_vector("empty plaintext and associated data", "aead empty", 0, 0),
+        aead_vector("associated data only", "aead ad only", 96, 0),
+        aead_vector("short plaintext", "aead short", 96, 17),
+        aead_vector("plaintext longer than one XChaCha20 block", "aead long", 96, 200),
+    ];
+
+    let mut flipped_tag = aead_vector("tag with a flipped bit", "aead long", 96, 200);
+    flipped_tag.tag.0[0] ^= 1;
+    flipped_tag.valid = false;
+    vectors.push(flipped_tag);
+
+    let mut flipped_ciphertext = aead_vector("ciphertext with a flipped bit", "aead long", 96, 200);
+    flipped_ciphertext.ciphertext.0[199] ^= 0x80;
+    flipped_ciphertext.valid = false;
+    vectors.push(flipped_ciphertext);
+
+    let mut other_ad = aead_vector("tag over different associated data", "aead long", 96, 200);
+    other_ad.ad.0[95] ^= 1;
+    other_ad.valid = false;
+    vectors.push(other_ad);
+
+    vectors
+}
+
+fn key_exchange_vector(comment: &str, label: &str) -> KeyExchangeVector {
+    let sender_secret: [u8; 32] = bytes(&format!("{label} sender secret"));
+    let recipient_secret: [u8; 32] = bytes(&format!("{label} recipient secret"));
+    let ephemeral_secret: [u8; 32] = bytes(&format!("{label} ephemeral secret"));
+    let (ephemeral_identity, shared_secret) = SharedSecret::sender_with_rng(FixedRng(ephemeral_secret), &ZornIdentitySecret::from(sender_secret), &ZornIdentity::from(identity(&recipient_secret)))
+        .expect("Key exchange should be contributory, but");
+    KeyExchangeVector {
+        comment: comment.to_string(),
+        sender_secret: Hex::from(&sender_secret[..]),
+        recipient_secret: Some(Hex::from(&recipient_secret[..])),
+        ephemeral_secret: Hex::from(&ephemeral_secret[..]),
+        sender_identity: Hex::from(&identity(&sender_secret)[..]),
+        recipient_identity: Hex::from(&identity(&recipient_secret)[..]),
+        ephemeral_identity: Hex::from(&ephemeral_identity.as_bytes()[..]),
+        shared_secret: Some(Hex::from(&shared_secret.as_bytes()[..])),
+        error: None,
+    }
+}
+
+fn key_exchange_vectors() -> Vec<KeyExchangeVector> {
+    let mut vectors = vec![
+        key_exchange_vector("secrets are used as is, clamping is part of X25519", "key exchange 1"),
+        key_exchange_vector("second independent exchange", "key exchange 2"),
+    ];
+
+    for (comment, low_order) in [
+        ("recipient identity is the point of order 1", "0000000000000000000000000000000000000000000000000000000000000000"),
+        ("recipient identity is a point of order 8", "e0eb7a7c3b41b8ae1656e3faf19fc46ada098deb9c32b1fd866205165f49b800"),
+    ] {
+        let mut v = key_exchange_vector(comment, "key exchange low order");
+        v.recipient_secret = None;
+        v.recipient_identity = Hex(hex::decode(low_order).expect("Literal should be valid hex, but"));
+        v.shared_secret = None;
+        v.error = Some(ExpectedError::NonContributory);
+        vectors.push(v);
+    }
+
+    vectors
+}
+
+// Builds a file directly from the format description, independently of
+// zorn_core::stream
+struct Sender {
+    aead: XChaCha20Blake3,
+    ad: Vec<u8>,
+    header: Vec<u8>,
+    // Padded files have the v2 version line and are otherwise the same
+    padded_header: Vec<u8>,
+}
+
+impl Sender {
+    fn new(sender_secret: &[u8; 32], recipient_secret: &[u8; 32], ephemeral_secret: &[u8; 32]) -> Self {
+        let (ephemeral_identity, shared_secret) = SharedSecret::sender_with_rng(FixedRng(*ephemeral_secret), &ZornIdentitySecret::from(*sender_secret), &ZornIdentity::from(identity(recipient_secret)))
+            .expect("Key exchange should be contributory, but");
+        let ad = [&ephemeral_identity.as_bytes()[..], &identity(sender_secret), &identity(recipient_secret)].concat();
+        let header = [&b"zorn-encryption.org/v1\n"[..], ephemeral_identity.as_bytes()].concat();
+        let padded_header = [&b"zorn-encryption.org/v2\n"[..], ephemeral_identity.as_bytes()].concat();
+        Sender { aead: XChaCha20Blake3::new(GenericArray::from_slice(shared_secret.as_bytes())), ad, header, padded_header }
+    }
+
+    fn chunk(&self, n: u64, last: bool, padded: bool, plaintext: &[u8]) -> Vec<u8> {
+        let flags = u64::from(last) | u64::from(padded) << 1;
+        let nonce = [flags.to_le_bytes(), [0u8; 8], n.to_le_bytes()].concat();
+        let mut buffer = plaintext.to_vec();
+        let tag = self.aead.encrypt_in_place_detached(GenericArray::from_slice(&nonce), &self.ad, &mut buffer)
+            .expect("Chunk should not be too long, but");
+        buffer.extend_from_slice(&tag);
+        buffer
+    }
+
+    fn chunks(&self, plaintext: &[u8]) -> Vec<Vec<u8>> {
+        self.chunks_padded_after(plaintext, plaintext.len())
+    }
+
+    // The first data_len octets are the message, any chunk ending past them holds padding
+    fn chunks_padded_after(&self, plaintext: &[u8], data_len: usize) -> Vec<Vec<u8>> {
+        if plaintext.is_empty() {
+            return vec![self.chunk(0, true, false, &[])];
+        }
+        let count = plaintext.chunks(CHUNK_SIZE).count();
+        plaintext.chunks(CHUNK_SIZE).enumerate()
+            .map(|(n, p)| self.chunk(n as u64, n == count - 1, n * CHUNK_SIZE + p.len() > data_len, p))
+            .collect()
+    }
+
+    fn padded_chunks(&self, len: usize, padded_len: usize) -> Vec<Vec<u8>> {
+        let mut padded = plaintext(len);
+        padded.push(PADDING_MARKER);
+        padded.resize(padded_len, 0);
+        self.chunks_padded_after(&padded, len)
+    }
+}
+
+struct Parties {
+    sender_secret: [u8; 32],
+    recipient_secret: [u8; 32],
+    ephemeral_secret: [u8; 32],
+}
+
+impl Parties {
+    fn new(label: &str) -> Self {
+        Parties {
+            sender_secret: bytes(&format!("{label} sender secret")),
+            recipient_secret: bytes(&format!("{label} recipient secret")),
+            ephemeral_secret: bytes(&format!("{label} ephemeral secret")),
+        }
+    }
+
+    fn sender(&self) -> Sender {
+        Sender::new(&self.sender_secret, &self.recipient_secret, &self.ephemeral_secret)
+    }
+
+    fn vector(&self, comment: &str, plaintext_len: Option<usize>, file: Vec<u8>, error: Option<ExpectedError>) -> FileVector {
+        FileVector {
+            comment: comment.to_string(),
+            sender_secret: Hex::from(&self.sender_secret[..]),
+            recipient_secret: Hex::from(&self.recipient_secret[..]),
+            ephemeral_secret: Hex::from(&self.ephemeral_secret[..]),
+            plaintext_len,
+            padded_len: None,
+            file: Hex(file),
+            error,
+        }
+    }
+
+    fn valid(&self, comment: &str, len: usize, padding: Option<Padding>) -> FileVector {
+        let sender = self.sender();
+        let padded_len = padding.map(|padding| padding.padded_len(len as u64).expect("Plaintext should fit the padding, but"));
+        let (header, chunks) = match padded_len {
+            Some(padded_len) => (sender.padded_header.clone(), sender.padded_chunks(len, padded_len as usize)),
+            None => (sender.header.clone(), sender.chunks(&plaintext(len))),
+        };
+        let file = [vec![header], chunks].concat().concat();
+
+        // The independent construction has to agree with the implementation
+        let mut encrypted = Encryptor::with_rng(Vec::new(), FixedRng(self.ephemeral_secret), &ZornIdentitySecret::from(self.sender_secret), &ZornIdentity::from(identity(&self.recipient_secret)))
+            .expect("Key exchange should succeed, but");
+        if let Some(padding) = padding {
+            encrypted = encrypted.with_padding(padding);
+        }
+        encrypted.write_all(&plaintext(len)).expect("Writing to a Vec should succeed, but");
+        assert!(encrypted.finish().expect("Writing to a Vec should succeed, but") == file, "zorn_core disagrees with the format description for {comment}");
+
+        FileVector { padded_len, ..self.vector(comment, Some(len), file, None) }
+    }
+}
+
+fn file_vectors() -> Vec<FileVector> {
+    let parties = Parties::new("files");
+    let sender = parties.sender();
+    let mut vectors = vec![
+        parties.valid("empty plaintext, a single empty final chunk", 0, None),
+        parties.valid("exactly one full chunk, which is the final chunk", CHUNK_SIZE, None),
+        parties.valid("one full chunk followed by a final chunk of one octet", CHUNK_SIZE + 1, None),
+        parties.valid("two full chunks followed by a short final chunk", 2 * CHUNK_SIZE + 17, None),
+        parties.valid("Padme padding from 1001 to 1024 octets within the final chunk", 1000, Some(Padding::Padme)),
+        parties.valid("empty plaintext padded, the final chunk is the marker alone", 0, Some(Padding::To(1))),
+        parties.valid("padding starting a full chunk and followed by a final chunk of one zero octet", 100, Some(Padding::To(CHUNK_SIZE as u64 + 1))),
+        parties.valid("one full chunk of plaintext followed by a final chunk with the marker alone", CHUNK_SIZE, Some(Padding::To(CHUNK_SIZE as u64 + 1))),
+    ];
+
+    let two_chunks = sender.chunks(&plaintext(CHUNK_SIZE + 1));
+    let one_chunk = sender.chunks(&plaintext(CHUNK_SIZE));
+    let short = sender.chunks(&plaintext(17));
+    let padded = sender.padded_chunks(100, CHUNK_SIZE + 1);
+    let header = &sender.header;
+    let padded_header = &sender.padded_header;
+
+    vectors.extend([
+        parties.vector("short final chunk sealed with the final flag unset", None, [header.clone(), sender.chunk(0, false, false, &plaintext(17))].concat(), Some(ExpectedError::Truncated)),
+        parties.vector("full chunk sealed with the final flag unset and nothing following it", None, [header.clone(), sender.chunk(0, false, false, &plaintext(CHUNK_SIZE))].concat(), Some(ExpectedError::Truncated)),
+        parties.vector("empty chunk sealed with the final flag unset", None, [header.clone(), sender.chunk(0, false, false, &[])].concat(), Some(ExpectedError::Truncated)),
+        parties.vector("final chunk dropped at a chunk boundary", None, [header.clone(), two_chunks[0].clone()].concat(), Some(ExpectedError::Truncated)),
+        parties.vector("final chunk cut short by one octet", None, [header.clone(), two_chunks[0].clone(), two_chunks[1][..two_chunks[1].len() - 1].to_vec()].concat(), Some(ExpectedError::Authentication)),
+        parties.vector("payload shorter than a tag", None, [header.clone(), two_chunks[1][..31].to_vec()].concat(), Some(ExpectedError::Truncated)),
+        parties.vector("header without a payload", None, header.clone(), Some(ExpectedError::Truncated)),
+        parties.vector("header cut short in the ephemeral identity", None, header[..54].to_vec(), Some(ExpectedError::Header)),
+        parties.vector("chunks in reverse order", None, [header.clone(), two_chunks[1].clone(), two_chunks[0].clone()].concat(), Some(ExpectedError::Authentication)),
+        parties.vector("one octet following the final chunk", None, [header.clone(), one_chunk[0].clone(), vec![0]].concat(), Some(ExpectedError::TrailingData)),
+        parties.vector("short final chunk repeated, which reads as a single longer chunk", None, [header.clone(), short[0].clone(), short[0].clone()].concat(), Some(ExpectedError::Authentication)),
+        parties.vector("unknown version v3", None, [b"zorn-encryption.org/v3\n".to_vec(), header[23..].to_vec(), short[0].clone()].concat(), Some(ExpectedError::Header)),
+        parties.vector("version line ending in CR LF", None, [b"zorn-encryption.org/v1\r\n".to_vec(), header[23..].to_vec(), short[0].clone()].concat(), Some(ExpectedError::Header)),
+        parties.vector("version line without the zorn-encryption.org/ prefix", None, [b"age-encryption.org/v1\n".to_vec(), header[23..].to_vec(), short[0].clone()].concat(), Some(ExpectedError::Header)),
+        parties.vector("padded final chunk without a marker", None, [padded_header.clone(), sender.chunk(0, true, true, &plaintext(17))].concat(), Some(ExpectedError::Padding)),
+        parties.vector("empty padded final chunk", None, [padded_header.clone(), sender.chunk(0, true, true, &[])].concat(), Some(ExpectedError::Padding)),
+        parties.vector("padding followed by a final chunk of zeros without the padding flag", None, [padded_header.clone(), padded[0].clone(), sender.chunk(1, true, false, &[0])].concat(), Some(ExpectedError::Authentication)),
+        parties.vector("padding followed by a final chunk of padding that is not zero", None, [padded_header.clone(), padded[0].clone(), sender.chunk(1, true, true, &[1])].concat(), Some(ExpectedError::Padding)),
+        parties.vector("padded chunks under the v1 version line", None, [header.clone(), padded[0].clone(), padded[1].clone()].concat(), Some(ExpectedError::Authentication)),
+        parties.vector("v2 version line on a file without padding", None, [padded_header.clone(), short[0].clone()].concat(), Some(ExpectedError::Padding)),
+    ]);
+
+    vectors
+}
+
+fn elligator2_vector(comment: &str, representative: [u8; 32]) -> Elligator2Vector {
+    Elligator2Vector {
+        comment: comment.to_string(),
+        representative: Hex::from(&representative[..]),
+        ephemeral_identity: Hex::from(&ephemeral_identity(&representative).as_bytes()[..]),
+    }
+}
+
+fn elligator2_vectors() -> Vec<Elligator2Vector> {
+    let mut representative: [u8; 32] = bytes("elligator2 1");
+    representative[31] &= 0x7f;
+    let mut high_bit = representative;
+    high_bit[31] |= 0x80;
+    let mut unreduced = [0xff; 32];
+    unreduced[31] = 0x7f;
+
+    vec![
+        elligator2_vector("zero represents u = 0", [0u8; 32]),
+        elligator2_vector("representative with the high bit clear", representative),
+        elligator2_vector("the same representative with the high bit set, which is ignored", high_bit),
+        elligator2_vector("second independent representative", bytes("elligator2 2")),
+        elligator2_vector("representative 2^255 - 1, which is reduced modulo p to 18", unreduced),
+    ]
+}
+
+fn main() -> std::io::Result<()> {
+    let dir = std::env::args_os().nth(1).map(PathBuf::from).unwrap_or_else(|| PathBuf::from(VECTORS_DIR));
+    fs::create_dir_all(&dir)?;
+
+    write(&dir, AEAD_FILE, "XChaCha20-BLAKE3 with the tag detached from the ciphertext. Vectors with valid false MUST fail to decrypt.", aead_vectors())?;
+    write(&dir, KEY_EXCHANGE_FILE,
+        "Sender and recipient key exchange. Secrets are the 32 octets read from the CSRNG before clamping. Vectors with an error MUST be rejected by the sender and, where recipient_secret is given, by the recipient.",
+        key_exchange_vectors())?;
+    write(&dir, FILES_FILE,
+        "Complete zorn files. Octet i of the plaintext is i mod 251. Valid files decrypt to plaintext_len octets and are reproduced exactly by encrypting with the given ephemeral secret, padded to padded_len octets where that is given, which makes them v2 files. Vectors with an error MUST be rejected, though authenticated chunks before the failure MAY have been released.",
+        file_vectors())?;
+    write(&dir, ELLIGATOR2_FILE,
+        "Ephemeral identities of uniform messages, decoded from their Elligator2 representatives.",
+        elligator2_vectors())?;
+
+    Ok(())
+}
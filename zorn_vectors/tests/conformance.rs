@@ -0,0 +1,131 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use aead::{AeadInPlace, KeyInit, generic_array::GenericArray};
+use serde::de::DeserializeOwned;
+use x25519_dalek::PublicKey;
+use zorn_core::Error;
+use zorn_core::identity::{ZornIdentity, ZornIdentitySecret};
+use zorn_core::key_exchange::{KeyExchangeError, SharedSecret};
+use zorn_core::padding::Padding;
+use zorn_core::stream::{Decryptor, Encryptor};
+use zorn_core::uniform::ephemeral_identity;
+use zorn_core::xchacha20_blake3::XChaCha20Blake3;
+use zorn_vectors::{AeadVector, Elligator2Vector, ExpectedError, FileVector, FixedRng, KeyExchangeVector, VectorFile, AEAD_FILE, ELLIGATOR2_FILE, FILES_FILE, FORMAT, KEY_EXCHANGE_FILE, VECTORS_DIR, plaintext};
+
+fn load<T: DeserializeOwned>(name: &str) -> Vec<T> {
+    let json = fs::read_to_string(Path::new(VECTORS_DIR).join(name)).expect("Vector file should be readable, but");
+    let file: VectorFile<T> = serde_json::from_str(&json).expect("Vector file should be valid, but");
+    assert_eq!(file.format, FORMAT);
+    assert!(!file.vectors.is_empty(), "{name} contains no vectors");
+    file.vectors
+}
+
+fn expected_error(e: &Error) -> Option<ExpectedError> {
+    match e {
+        Error::Header(_) => Some(ExpectedError::Header),
+        Error::KeyExchange(KeyExchangeError::NonContributory) => Some(ExpectedError::NonContributory),
+        Error::Authentication { .. } => Some(ExpectedError::Authentication),
+        Error::Truncated { .. } => Some(ExpectedError::Truncated),
+        Error::TrailingData => Some(ExpectedError::TrailingData),
+        Error::Padding { .. } => Some(ExpectedError::Padding),
+        _ => None,
+    }
+}
+
+#[test]
+fn xchacha20_blake3() {
+    for v in load::<AeadVector>(AEAD_FILE) {
+        let aead = XChaCha20Blake3::new(GenericArray::from_slice(&v.key.0));
+        let nonce = GenericArray::from_slice(&v.nonce.0);
+
+        if v.valid {
+            let mut buffer = v.plaintext.0.clone();
+            let tag = aead.encrypt_in_place_detached(nonce, &v.ad.0, &mut buffer).expect("Encryption should succeed, but");
+            assert_eq!(buffer, v.ciphertext.0, "{}", v.comment);
+            assert_eq!(tag.as_slice(), v.tag.0, "{}", v.comment);
+        }
+
+        let mut buffer = v.ciphertext.0.clone();
+        let result = aead.decrypt_in_place_detached(nonce, &v.ad.0, &mut buffer, GenericArray::from_slice(&v.tag.0));
+        assert_eq!(result.is_ok(), v.valid, "{}", v.comment);
+        if v.valid {
+            assert_eq!(buffer, v.plaintext.0, "{}", v.comment);
+        }
+    }
+}
+
+#[test]
+fn key_exchange() {
+    for v in load::<KeyExchangeVector>(KEY_EXCHANGE_FILE) {
+        let sender_secret = ZornIdentitySecret::from(v.sender_secret.to_array());
+        let sender_identity = ZornIdentity::from(&sender_secret);
+        let recipient_identity = ZornIdentity::from(v.recipient_identity.to_array());
+        assert_eq!(sender_identity.as_bytes(), &v.sender_identity.to_array(), "{}", v.comment);
+
+        let sender = SharedSecret::sender_with_rng(FixedRng(v.ephemeral_secret.to_array()), &sender_secret, &recipient_identity);
+        let recipient = v.recipient_secret.as_ref().map(|s| {
+            let recipient_secret = ZornIdentitySecret::from(s.to_array());
+            assert_eq!(ZornIdentity::from(&recipient_secret), recipient_identity, "{}", v.comment);
+            SharedSecret::recipient(&recipient_secret, &sender_identity, &PublicKey::from(v.ephemeral_identity.to_array()))
+        });
+
+        match (&v.shared_secret, v.error) {
+            (Some(shared_secret), None) => {
+                let (ephemeral_identity, sender) = sender.expect("Key exchange should succeed, but");
+                assert_eq!(ephemeral_identity.as_bytes(), &v.ephemeral_identity.to_array(), "{}", v.comment);
+                assert_eq!(sender.as_bytes(), &shared_secret.to_array(), "{}", v.comment);
+                let recipient = recipient.expect("Valid vectors should include the recipient secret, but").expect("Key exchange should succeed, but");
+                assert_eq!(recipient.as_bytes(), &shared_secret.to_array(), "{}", v.comment);
+            }
+            (None, Some(ExpectedError::NonContributory)) => {
+                assert!(matches!(sender, Err(KeyExchangeError::NonContributory)), "{}", v.comment);
+                assert!(recipient.is_none_or(|r| matches!(r, Err(KeyExchangeError::NonContributory))), "{}", v.comment);
+            }
+            _ => panic!("Unexpected outcome in key exchange vector {}", v.comment),
+        }
+    }
+}
+
+#[test]
+fn files() {
+    for v in load::<FileVector>(FILES_FILE) {
+        let sender_secret = ZornIdentitySecret::from(v.sender_secret.to_array());
+        let recipient_secret = ZornIdentitySecret::from(v.recipient_secret.to_array());
+
+        let decrypted = Decryptor::new(&v.file.0[..], &recipient_secret, &ZornIdentity::from(&sender_secret))
+            .and_then(|mut d| {
+                let mut decrypted = Vec::new();
+                d.read_to_end(&mut decrypted)?;
+                Ok(decrypted)
+            });
+
+        match (v.plaintext_len, v.error) {
+            (Some(len), None) => {
+                assert!(decrypted.expect("File should decrypt, but") == plaintext(len), "{}", v.comment);
+
+                let mut e = Encryptor::with_rng(Vec::new(), FixedRng(v.ephemeral_secret.to_array()), &sender_secret, &ZornIdentity::from(&recipient_secret))
+                    .expect("Key exchange should succeed, but");
+                // Padding to the padded length reproduces any padding scheme
+                if let Some(padded_len) = v.padded_len {
+                    e = e.with_padding(Padding::To(padded_len));
+                }
+                e.write_all(&plaintext(len)).expect("Writing to a Vec should succeed, but");
+                assert!(e.finish().expect("Writing to a Vec should succeed, but") == v.file.0, "{}", v.comment);
+            }
+            (None, Some(error)) => {
+                let e = decrypted.expect_err(&v.comment);
+                assert_eq!(expected_error(&e), Some(error), "{}: {e}", v.comment);
+            }
+            _ => panic!("Unexpected outcome in file vector {}", v.comment),
+        }
+    }
+}
+
+#[test]
+fn elligator2() {
+    for v in load::<Elligator2Vector>(ELLIGATOR2_FILE) {
+        assert_eq!(ephemeral_identity(&v.representative.to_array()).as_bytes(), &v.ephemeral_identity.to_array(), "{}", v.comment);
+    }
+}